@@ -0,0 +1,72 @@
+use anyhow::Result;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+/// Turns keys and values into the bytes kept in the topic and the state store, and back.
+/// Partitions are chosen from the encoded key, so every instance must use the same key codec.
+pub(crate) trait Codec<T>: Send + Sync {
+    fn encode(&self, value: &T) -> Result<Vec<u8>>;
+
+    fn decode(&self, bytes: &[u8]) -> Result<T>;
+}
+
+// Bytes Codec
+// -----------
+
+/// Keeps bytes as they are, and strings as their UTF-8 bytes.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct BytesCodec;
+
+impl Codec<Vec<u8>> for BytesCodec {
+    fn encode(&self, value: &Vec<u8>) -> Result<Vec<u8>> {
+        Ok(value.clone())
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        Ok(bytes.to_vec())
+    }
+}
+
+impl Codec<String> for BytesCodec {
+    fn encode(&self, value: &String) -> Result<Vec<u8>> {
+        Ok(value.as_bytes().to_vec())
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<String> {
+        Ok(String::from_utf8(bytes.to_vec())?)
+    }
+}
+
+// JSON Codec
+// ----------
+
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct JsonCodec;
+
+impl<T: Serialize + DeserializeOwned> Codec<T> for JsonCodec {
+    fn encode(&self, value: &T) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(value)?)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<T> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+// CBOR Codec
+// ----------
+
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct CborCodec;
+
+impl<T: Serialize + DeserializeOwned> Codec<T> for CborCodec {
+    fn encode(&self, value: &T) -> Result<Vec<u8>> {
+        let mut bytes = vec![];
+        ciborium::into_writer(value, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<T> {
+        Ok(ciborium::from_reader(bytes)?)
+    }
+}
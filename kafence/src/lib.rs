@@ -1,34 +1,79 @@
 use anyhow::Result;
-use rdkafka::admin::{AdminClient, AdminOptions, NewTopic, TopicReplication};
-use rdkafka::client::{ClientContext, DefaultClientContext};
-use rdkafka::config::ClientConfig;
-use rdkafka::consumer::{
-    BaseConsumer, CommitMode, Consumer, ConsumerContext, DefaultConsumerContext, Rebalance,
-    StreamConsumer,
-};
-use rdkafka::error::KafkaError;
-use rdkafka::error::RDKafkaErrorCode;
-use rdkafka::message::{BorrowedMessage, Message, ToBytes};
-use rdkafka::producer::{FutureProducer, FutureRecord};
-use rocksdb::{DB, Options};
 use std::collections::{HashMap, HashSet, hash_map::Entry};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
 use std::time::Duration;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
-use tokio::sync::oneshot;
-use tokio::task;
+use tokio::sync::{oneshot, watch};
+use tokio::task::{self, JoinHandle, JoinSet};
+
+mod codec;
+mod metrics;
+mod router;
+mod store;
+mod transport;
+
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use codec::{BytesCodec, Codec};
+use metrics::KafenceMetrics;
+use router::{
+    ABSENT_VERSION, BASE64_KEY_ENCODING, IF_VALUE_HEADER, IF_VERSION_HEADER, KafenceError,
+    KafenceErrorCode, KafenceRouter, MULTI_GET_PATH, PROXIED_HEADER, RECORD_KEY_ENCODING_HEADER,
+    RECORD_KEY_HEADER, RECORD_PREFIX_HEADER, RECORDS_PATH, ROUTE_EPOCH_HEADER,
+    ROUTE_PARTITIONS_HEADER, VERSION_HEADER,
+};
+use serde::{Deserialize, Serialize};
+use store::{
+    KafenceEntry, RocksDbStore, StateStore, delete_version, is_reserved_key, store_offset,
+    store_version, stored_offset, stored_version,
+};
+use tokio::time::{Instant, MissedTickBehavior};
+use tracing::{debug, info, warn};
+use transport::{KafkaTransport, RebalanceListener, Transport, TransportConsumer, TransportRecord};
 
 const ROCKSDB_PATH: &str = "./state/orders-store";
 
-type MaterializerAck = Arc<RwLock<HashMap<String, Vec<oneshot::Sender<()>>>>>;
+const ROUTE_ATTEMPTS: u32 = 5;
+
+const ROUTE_RETRY_BACKOFF: Duration = Duration::from_millis(200);
+
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+const SHUTDOWN_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+const CONSUMER_LAG_INTERVAL: Duration = Duration::from_secs(5);
+
+type LocalStateStore = Arc<OnceLock<Box<dyn StateStore>>>;
+
+type KafenceTransport = Arc<OnceLock<Arc<dyn Transport>>>;
+
+type RouteTable = Arc<RwLock<HashMap<String, RouteEntry>>>;
+
+type LocalAssignment = Arc<RwLock<PartitionAssignment>>;
+
+type StreamState = Arc<watch::Sender<KafenceState>>;
 
-struct KafenceProducer {
-    producer: FutureProducer,
+/// Value of a key, with the version of its record.
+type VersionedValue = (Vec<u8>, u64);
+
+/// Partition with the epoch of the route the caller used for it.
+type RoutedPartition = (i32, u64);
+
+type MaterializerAck = Arc<RwLock<HashMap<Vec<u8>, Vec<oneshot::Sender<()>>>>>;
+
+struct KafenceProducer<K = String, V = Vec<u8>> {
+    transport: Arc<dyn Transport>,
     service_url: String,
     topic_router: String,
     partitions: i32,
-    route_table: Arc<RwLock<HashMap<String, String>>>,
+    route_table: RouteTable,
+    assignment: LocalAssignment,
     materializer_ack: MaterializerAck,
+    state_store: LocalStateStore,
+    state: StreamState,
+    metrics: Arc<KafenceMetrics>,
+    key_codec: Arc<dyn Codec<K>>,
+    value_codec: Arc<dyn Codec<V>>,
 }
 
 #[derive(Debug)]
@@ -52,72 +97,65 @@ struct Kafence {
     consumer_group: String,
     topic_router: String,
     routed_consumer_group: String,
-    route_table: Arc<RwLock<HashMap<String, String>>>,
+    route_table: RouteTable,
+    assignment: LocalAssignment,
     materializer_ack: MaterializerAck,
+    materializer_lock: Arc<Mutex<()>>,
     partitions: i32,
     rocksdb_path: String,
+    state_store: LocalStateStore,
+    transport: KafenceTransport,
     serviice_url: String,
-}
-
-struct KafenceConsumerContext {
-    topic: String,
-    service_host: String,
-    router_channel_sender: UnboundedSender<RouteInfo>,
+    standby: bool,
+    state: StreamState,
+    metrics: Arc<KafenceMetrics>,
 }
 
 #[derive(Clone, Debug)]
 struct RouteInfo {
     paritions: Arc<RwLock<HashSet<i32>>>,
     service_host: String,
+    epoch: u64,
 }
 
-impl ClientContext for KafenceConsumerContext {}
-
-impl ConsumerContext for KafenceConsumerContext {
-    fn post_rebalance(&self, consumer: &BaseConsumer<Self>, rebalance: &Rebalance<'_>) {
-        println!("Pre rebalance {:?}", rebalance);
-        match rebalance {
-            Rebalance::Assign(_) | Rebalance::Revoke(_) => {
-                let partitions = consumer
-                    .assignment()
-                    .map(|tp_list| {
-                        tp_list
-                            .elements()
-                            .iter()
-                            .filter(|tp| tp.topic() == self.topic)
-                            .map(|tp| {
-                                println!(
-                                    "Topic: {} new partition assigned {}",
-                                    tp.topic(),
-                                    tp.partition()
-                                );
-                                tp.partition()
-                            })
-                            .collect::<HashSet<_>>()
-                    })
-                    .unwrap_or_default();
-                let route_info = RouteInfo {
-                    paritions: Arc::new(RwLock::new(partitions)),
-                    service_host: self.service_host.clone(),
-                };
-                if !self.router_channel_sender.is_closed() {
-                    match self.router_channel_sender.send(route_info) {
-                        Ok(_) => println!("Router channel sent"),
-                        Err(e) => println!("Router channel send failed {}", e),
-                    }
-                }
-            }
-            Rebalance::Error(e) => {
-                println!("rebalance error: {e}");
-            }
-        }
-    }
+/// Owner of a partition, with the generation of the consumer group the partition was assigned in.
+/// An entry only replaces another one with the same or a lower epoch, so a late route of a previous
+/// owner cannot take the partition back. An entry without host is the tombstone of the route with
+/// its epoch, and is fenced the same way.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct RouteEntry {
+    host: String,
+    epoch: u64,
+}
+
+/// Partitions assigned to this instance by the last rebalance.
+#[derive(Clone, Debug, Default)]
+struct PartitionAssignment {
+    partitions: HashSet<i32>,
+    epoch: u64,
+}
+
+/// Writes are only accepted while running. Once draining, the writes in flight are still
+/// materialized, and once stopped the streams commit and leave.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum KafenceState {
+    Running,
+    Draining,
+    Stopped,
+}
+
+/// Streams started by [Kafence::stream].
+struct KafenceHandle {
+    kafence: Kafence,
+    tasks: Vec<(&'static str, JoinHandle<Result<()>>)>,
 }
 
 impl Kafence {
     fn new() -> Kafence {
+        let client_id = uuid::Uuid::new_v4().to_string();
+        let metrics = Arc::new(KafenceMetrics::new(&client_id));
         Kafence {
-            client_id: uuid::Uuid::new_v4().to_string(),
+            client_id,
             brokers: "".to_string(),
             consumer_group: "".to_string(),
             routed_consumer_group: "".to_string(),
@@ -125,9 +163,16 @@ impl Kafence {
             topic: "".to_string(),
             topic_router: "".to_string(),
             route_table: Arc::new(RwLock::new(HashMap::new())),
+            assignment: Arc::new(RwLock::new(PartitionAssignment::default())),
             materializer_ack: Arc::new(RwLock::new(HashMap::new())),
+            materializer_lock: Arc::new(Mutex::new(())),
             rocksdb_path: ROCKSDB_PATH.to_string(),
+            state_store: Arc::new(OnceLock::new()),
+            transport: Arc::new(OnceLock::new()),
             serviice_url: "".to_string(),
+            standby: false,
+            state: Arc::new(watch::Sender::new(KafenceState::Running)),
+            metrics,
         }
     }
 
@@ -140,10 +185,17 @@ impl Kafence {
             consumer_group: self.consumer_group,
             routed_consumer_group: self.routed_consumer_group,
             route_table: self.route_table,
+            assignment: self.assignment,
             materializer_ack: self.materializer_ack,
+            materializer_lock: self.materializer_lock,
             partitions: self.partitions,
             rocksdb_path: self.rocksdb_path,
+            state_store: self.state_store,
+            transport: self.transport,
             serviice_url: self.serviice_url,
+            standby: self.standby,
+            state: self.state,
+            metrics: self.metrics,
         }
     }
 
@@ -158,11 +210,18 @@ impl Kafence {
             topic_router,
             routed_consumer_group,
             route_table: self.route_table,
+            assignment: self.assignment,
             materializer_ack: self.materializer_ack,
+            materializer_lock: self.materializer_lock,
             consumer_group: self.consumer_group,
             partitions: self.partitions,
             rocksdb_path: self.rocksdb_path,
+            state_store: self.state_store,
+            transport: self.transport,
             serviice_url: self.serviice_url,
+            standby: self.standby,
+            state: self.state,
+            metrics: self.metrics,
         }
     }
     fn with_consumer_group(self, consumer_group: &str) -> Kafence {
@@ -174,10 +233,17 @@ impl Kafence {
             consumer_group: consumer_group.to_string(),
             routed_consumer_group: self.routed_consumer_group,
             route_table: self.route_table,
+            assignment: self.assignment,
             materializer_ack: self.materializer_ack,
+            materializer_lock: self.materializer_lock,
             partitions: self.partitions,
             rocksdb_path: self.rocksdb_path,
+            state_store: self.state_store,
+            transport: self.transport,
             serviice_url: self.serviice_url,
+            standby: self.standby,
+            state: self.state,
+            metrics: self.metrics,
         }
     }
 
@@ -190,10 +256,17 @@ impl Kafence {
             consumer_group: self.consumer_group,
             routed_consumer_group: self.routed_consumer_group,
             route_table: self.route_table,
+            assignment: self.assignment,
             materializer_ack: self.materializer_ack,
+            materializer_lock: self.materializer_lock,
             partitions,
             rocksdb_path: self.rocksdb_path,
+            state_store: self.state_store,
+            transport: self.transport,
             serviice_url: self.serviice_url,
+            standby: self.standby,
+            state: self.state,
+            metrics: self.metrics,
         }
     }
 
@@ -206,10 +279,17 @@ impl Kafence {
             consumer_group: self.consumer_group,
             routed_consumer_group: self.routed_consumer_group,
             route_table: self.route_table,
+            assignment: self.assignment,
             materializer_ack: self.materializer_ack,
+            materializer_lock: self.materializer_lock,
             partitions: self.partitions,
             rocksdb_path: rocksdb_path.to_string(),
+            state_store: self.state_store,
+            transport: self.transport,
             serviice_url: self.serviice_url,
+            standby: self.standby,
+            state: self.state,
+            metrics: self.metrics,
         }
     }
 
@@ -222,49 +302,144 @@ impl Kafence {
             consumer_group: self.consumer_group,
             routed_consumer_group: self.routed_consumer_group,
             route_table: self.route_table,
+            assignment: self.assignment,
             materializer_ack: self.materializer_ack,
+            materializer_lock: self.materializer_lock,
             partitions: self.partitions,
             rocksdb_path: self.rocksdb_path,
+            state_store: self.state_store,
+            transport: self.transport,
             serviice_url: service_url.to_string(),
+            standby: self.standby,
+            state: self.state,
+            metrics: self.metrics,
+        }
+    }
+
+    /// Keep a shadow copy of the partitions owned by other instances, so this instance has little to
+    /// restore when it takes one of them over.
+    fn with_standby(self, standby: bool) -> Kafence {
+        Kafence {
+            client_id: self.client_id,
+            brokers: self.brokers,
+            topic: self.topic,
+            topic_router: self.topic_router,
+            consumer_group: self.consumer_group,
+            routed_consumer_group: self.routed_consumer_group,
+            route_table: self.route_table,
+            assignment: self.assignment,
+            materializer_ack: self.materializer_ack,
+            materializer_lock: self.materializer_lock,
+            partitions: self.partitions,
+            rocksdb_path: self.rocksdb_path,
+            state_store: self.state_store,
+            transport: self.transport,
+            serviice_url: self.serviice_url,
+            standby,
+            state: self.state,
+            metrics: self.metrics,
+        }
+    }
+
+    fn with_state_store<S: StateStore + 'static>(self, state_store: S) -> Kafence {
+        let state_store: Box<dyn StateStore> = Box::new(state_store);
+        Kafence {
+            client_id: self.client_id,
+            brokers: self.brokers,
+            topic: self.topic,
+            topic_router: self.topic_router,
+            consumer_group: self.consumer_group,
+            routed_consumer_group: self.routed_consumer_group,
+            route_table: self.route_table,
+            assignment: self.assignment,
+            materializer_ack: self.materializer_ack,
+            materializer_lock: self.materializer_lock,
+            partitions: self.partitions,
+            rocksdb_path: self.rocksdb_path,
+            state_store: Arc::new(OnceLock::from(state_store)),
+            transport: self.transport,
+            serviice_url: self.serviice_url,
+            standby: self.standby,
+            state: self.state,
+            metrics: self.metrics,
+        }
+    }
+
+    fn with_transport<T: Transport + 'static>(self, transport: T) -> Kafence {
+        let transport: Arc<dyn Transport> = Arc::new(transport);
+        Kafence {
+            client_id: self.client_id,
+            brokers: self.brokers,
+            topic: self.topic,
+            topic_router: self.topic_router,
+            consumer_group: self.consumer_group,
+            routed_consumer_group: self.routed_consumer_group,
+            route_table: self.route_table,
+            assignment: self.assignment,
+            materializer_ack: self.materializer_ack,
+            materializer_lock: self.materializer_lock,
+            partitions: self.partitions,
+            rocksdb_path: self.rocksdb_path,
+            state_store: self.state_store,
+            transport: Arc::new(OnceLock::from(transport)),
+            serviice_url: self.serviice_url,
+            standby: self.standby,
+            state: self.state,
+            metrics: self.metrics,
         }
     }
 
-    async fn stream(&self) -> Result<()> {
+    async fn stream(&self) -> Result<KafenceHandle> {
         let (sender, recv): (UnboundedSender<RouteInfo>, UnboundedReceiver<RouteInfo>) =
             tokio::sync::mpsc::unbounded_channel();
         let kafence_stream = self.clone();
         let stream_task = task::spawn(async move { kafence_stream.create_stream(sender).await });
-        tokio::time::sleep(Duration::from_secs(2)).await;
         let kafence_route_table = self.clone();
         let stream_partition_owner_task =
             task::spawn(async move { kafence_route_table.create_routed_stream(recv).await });
-        assert!(
-            !stream_task.is_finished(),
-            "stream task stopped before producing"
-        );
-        assert!(
-            !stream_partition_owner_task.is_finished(),
-            "stream partition owner task stopped before producing"
-        );
-        Ok(())
+        let mut tasks = vec![
+            ("stream", stream_task),
+            ("stream partition owner", stream_partition_owner_task),
+        ];
+        if self.standby {
+            let kafence_standby = self.clone();
+            tasks.push((
+                "standby stream",
+                task::spawn(async move { kafence_standby.create_standby_stream().await }),
+            ));
+        }
+        Ok(KafenceHandle {
+            kafence: self.clone(),
+            tasks,
+        })
     }
 
     fn producer(&self) -> Result<KafenceProducer, KafenceProducerError> {
-        match ClientConfig::new()
-            .set("bootstrap.servers", &self.brokers)
-            .set("message.timeout.ms", "5000")
-            .create()
-        {
-            Ok(producer) => Ok(KafenceProducer {
-                producer: producer,
+        self.typed_producer(BytesCodec, BytesCodec)
+    }
+
+    fn typed_producer<K, V>(
+        &self,
+        key_codec: impl Codec<K> + 'static,
+        value_codec: impl Codec<V> + 'static,
+    ) -> Result<KafenceProducer<K, V>, KafenceProducerError> {
+        match self.transport() {
+            Ok(transport) => Ok(KafenceProducer {
+                transport,
                 service_url: self.serviice_url.clone(),
                 topic_router: self.topic_router.clone(),
                 partitions: self.partitions,
                 route_table: self.route_table.clone(),
+                assignment: self.assignment.clone(),
                 materializer_ack: self.materializer_ack.clone(),
+                state_store: self.state_store.clone(),
+                state: self.state.clone(),
+                metrics: self.metrics.clone(),
+                key_codec: Arc::new(key_codec),
+                value_codec: Arc::new(value_codec),
             }),
             Err(e) => {
-                println!("Error creating Kafka producer. Caused by {}", e);
+                warn!(error = %e, "kafka producer not created");
                 Err(KafenceProducerError {
                     message: e.to_string(),
                 })
@@ -272,197 +447,552 @@ impl Kafence {
         }
     }
 
+    fn query(&self) -> KafenceQuery {
+        self.typed_query(BytesCodec, BytesCodec)
+    }
+
+    fn typed_query<K, V>(
+        &self,
+        key_codec: impl Codec<K> + 'static,
+        value_codec: impl Codec<V> + 'static,
+    ) -> KafenceQuery<K, V> {
+        KafenceQuery {
+            service_url: self.serviice_url.clone(),
+            topic_router: self.topic_router.clone(),
+            partitions: self.partitions,
+            route_table: self.route_table.clone(),
+            assignment: self.assignment.clone(),
+            state_store: self.state_store.clone(),
+            metrics: self.metrics.clone(),
+            key_codec: Arc::new(key_codec),
+            value_codec: Arc::new(value_codec),
+        }
+    }
+
+    fn router(&self) -> Result<KafenceRouter> {
+        let producer = self
+            .typed_producer(BytesCodec, BytesCodec)
+            .map_err(|e| anyhow::anyhow!("{}", e.message))?;
+        Ok(KafenceRouter::new(
+            &self.topic,
+            producer,
+            self.typed_query(BytesCodec, BytesCodec),
+            self.metrics.clone(),
+        ))
+    }
+
+    /// Metrics of this instance, served by the router on `/metrics`.
+    fn metrics(&self) -> Arc<KafenceMetrics> {
+        self.metrics.clone()
+    }
+
     fn build(self) -> Arc<Kafence> {
         Arc::new(self)
     }
 
+    fn transport(&self) -> Result<Arc<dyn Transport>> {
+        if self.transport.get().is_none() {
+            let transport: Arc<dyn Transport> = Arc::new(KafkaTransport::new(&self.brokers)?);
+            let _ = self.transport.set(transport);
+        }
+        Ok(self.transport.get().unwrap().clone())
+    }
+
     async fn create_stream(&self, sender: UnboundedSender<RouteInfo>) -> Result<()> {
-        let rocks_db = open_rocksdb(&self.rocksdb_path)?;
-        let context = KafenceConsumerContext {
-            topic: self.topic.to_string(),
-            service_host: self.serviice_url.to_string(),
-            router_channel_sender: sender,
+        let topic = self.topic.clone();
+        let service_host = self.serviice_url.clone();
+        let (assigned, assignments) = tokio::sync::mpsc::unbounded_channel();
+        let assignment = self.assignment.clone();
+        let on_rebalance: RebalanceListener = Arc::new(move |partitions, epoch| {
+            // Revoked partitions are released at once, assigned ones only once they are restored.
+            assignment
+                .write()
+                .unwrap()
+                .partitions
+                .retain(|partition| partitions.contains(partition));
+            let _ = assigned.send(PartitionAssignment { partitions, epoch });
+        });
+        let assignment = self.assignment.clone();
+        let on_restored = move |restored: PartitionAssignment| {
+            *assignment.write().unwrap() = restored.clone();
+            let route_info = RouteInfo {
+                paritions: Arc::new(RwLock::new(restored.partitions)),
+                service_host: service_host.clone(),
+                epoch: restored.epoch,
+            };
+            if !sender.is_closed() {
+                match sender.send(route_info) {
+                    Ok(_) => debug!(topic = %topic, "route info sent to the router channel"),
+                    Err(e) => warn!(error = %e, "route info not sent to the router channel"),
+                }
+            }
         };
-        let stream_consumer = create_consumer(
+        let stream_consumer = self.transport()?.subscribe(
             &self.client_id,
-            &self.brokers,
             &self.consumer_group,
-            context,
+            &self.topic,
+            on_rebalance,
         )?;
-        stream_consumer.subscribe(&[self.topic.as_str()])?;
-        materialize_loop(
+        self.materialize_loop(stream_consumer.as_ref(), true, assignments, on_restored)
+            .await?;
+        // Still a member of the group, so the routes of the next owners are published after these.
+        self.assignment.write().unwrap().partitions.clear();
+        self.publish_route_tombstones().await
+    }
+
+    /// Remove the routes of the partitions owned by this instance, so writers stop sending it
+    /// requests until the next owners publish theirs. A tombstone is a route without host, with
+    /// the epoch of the removed route, so it never removes the route of a newer owner.
+    async fn publish_route_tombstones(&self) -> Result<()> {
+        let owned_routes = self
+            .route_table
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, route)| route.host == self.serviice_url)
+            .map(|(key, route)| (key.clone(), route.epoch))
+            .collect::<Vec<_>>();
+        let transport = self.transport()?;
+        for (key, epoch) in owned_routes {
+            let tombstone = serde_json::to_vec(&RouteEntry {
+                host: String::new(),
+                epoch,
+            })?;
+            info!(client_id = %self.client_id, key = %key, epoch, "route tombstone published");
+            transport
+                .send(&self.topic_router, None, key.as_bytes(), Some(&tombstone))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Materialize every partition of the topic in a consumer group of its own, without publishing
+    /// routes, so the store keeps up with the partitions owned by the other instances.
+    async fn create_standby_stream(&self) -> Result<()> {
+        let (assigned, assignments) = tokio::sync::mpsc::unbounded_channel();
+        let on_rebalance: RebalanceListener = Arc::new(move |partitions, epoch| {
+            let _ = assigned.send(PartitionAssignment { partitions, epoch });
+        });
+        let stream_consumer = self.transport()?.subscribe(
             &self.client_id,
-            &stream_consumer,
-            &rocks_db,
-            &self.materializer_ack,
+            &format!("{}_standby_{}", self.topic, self.client_id),
+            &self.topic,
+            on_rebalance,
+        )?;
+        let client_id = self.client_id.clone();
+        self.materialize_loop(
+            stream_consumer.as_ref(),
+            false,
+            assignments,
+            move |restored: PartitionAssignment| {
+                info!(client_id = %client_id, partitions = ?restored.partitions, "standby restored")
+            },
         )
         .await
     }
 
+    /// Materialize the assigned partitions of the topic. On every assignment the partitions are
+    /// consumed again from the offsets stored with the records, and `on_restored` is called once the
+    /// store has caught up with the end of all of them. Writers waiting for their records are only
+    /// acknowledged with `acknowledge`. Once stopped, the last offsets are committed synchronously.
+    async fn materialize_loop(
+        &self,
+        stream_consumer: &dyn TransportConsumer,
+        acknowledge: bool,
+        mut assignments: UnboundedReceiver<PartitionAssignment>,
+        on_restored: impl Fn(PartitionAssignment),
+    ) -> Result<()> {
+        let state_store = self.open_state_store()?;
+        let materializer_ack = acknowledge.then_some(&self.materializer_ack);
+        let mut positioned = HashSet::new();
+        let mut restoring: Option<(PartitionAssignment, HashMap<i32, i64>)> = None;
+        let mut materialized: HashMap<i32, TransportRecord> = HashMap::new();
+        let mut state = self.state.subscribe();
+        let stream = if acknowledge { "owner" } else { "standby" };
+        let mut consumer_lag = tokio::time::interval(CONSUMER_LAG_INTERVAL);
+        consumer_lag.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                biased;
+                _ = state.wait_for(|state| *state == KafenceState::Stopped) => {
+                    for (partition, message) in &materialized {
+                        if positioned.contains(partition) {
+                            stream_consumer.commit_sync(message)?;
+                        }
+                    }
+                    return Ok(());
+                }
+                Some(assignment) = assignments.recv() => {
+                    let pending = restore_positions(
+                        &self.topic,
+                        stream_consumer,
+                        state_store,
+                        &assignment,
+                    )?;
+                    let revoked = positioned
+                        .difference(&assignment.partitions)
+                        .copied()
+                        .collect::<HashSet<_>>();
+                    for partition in &revoked {
+                        let _ = self
+                            .metrics
+                            .consumer_lag
+                            .remove_label_values(&[stream, partition.to_string().as_str()]);
+                    }
+                    if let Some(materializer_ack) = materializer_ack {
+                        release_revoked_acks(materializer_ack, &revoked, self.partitions);
+                    }
+                    positioned = assignment.partitions.clone();
+                    if pending.is_empty() {
+                        restoring = None;
+                        on_restored(assignment);
+                    } else {
+                        info!(
+                            client_id = %self.client_id,
+                            partitions = ?pending,
+                            epoch = assignment.epoch,
+                            "restoring partitions"
+                        );
+                        restoring = Some((assignment, pending));
+                    }
+                }
+                _ = consumer_lag.tick() => {
+                    self.record_consumer_lag(stream_consumer, state_store, stream, &positioned);
+                }
+                message = stream_consumer.recv() => {
+                    let message = message?;
+                    if !positioned.contains(&message.partition) {
+                        // Consumed before the seek of its assignment, it is received again after it.
+                        continue;
+                    }
+                    materialize_state_store(
+                        &self.client_id,
+                        &message,
+                        state_store,
+                        &self.materializer_lock,
+                        materializer_ack,
+                    )?;
+                    stream_consumer.commit(&message)?;
+                    let partition = message.partition;
+                    materialized.insert(partition, message);
+                    let restored = match &mut restoring {
+                        Some((_, pending)) => {
+                            if pending
+                                .get(&partition)
+                                .is_some_and(|end| materialized[&partition].offset + 1 >= *end)
+                            {
+                                pending.remove(&partition);
+                            }
+                            pending.is_empty()
+                        }
+                        None => false,
+                    };
+                    if let Some((assignment, _)) = restored.then(|| restoring.take()).flatten() {
+                        on_restored(assignment);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Records of each partition between the offset stored with the records and the end of the
+    /// partition.
+    fn record_consumer_lag(
+        &self,
+        stream_consumer: &dyn TransportConsumer,
+        state_store: &dyn StateStore,
+        stream: &str,
+        partitions: &HashSet<i32>,
+    ) {
+        for partition in partitions {
+            let lag = stored_offset(state_store, &self.topic, *partition).and_then(|offset| {
+                Ok(stream_consumer.end_offset(*partition)? - offset.unwrap_or(0))
+            });
+            match lag {
+                Ok(lag) => self
+                    .metrics
+                    .consumer_lag
+                    .with_label_values(&[stream, partition.to_string().as_str()])
+                    .set(lag.max(0)),
+                Err(e) => warn!(
+                    client_id = %self.client_id,
+                    partition,
+                    error = %e,
+                    "consumer lag not measured"
+                ),
+            }
+        }
+    }
+
+    fn open_state_store(&self) -> Result<&dyn StateStore> {
+        if self.state_store.get().is_none() {
+            let _ = self
+                .state_store
+                .set(Box::new(RocksDbStore::open(&self.rocksdb_path)?));
+        }
+        local_store(&self.state_store)
+    }
+
     async fn create_routed_stream(&self, recv: UnboundedReceiver<RouteInfo>) -> Result<()> {
-        create_route_topic_if_not_exists(&self.brokers, &self.topic_router).await?;
-        let stream_consumer = create_consumer(
+        let transport = self.transport()?;
+        transport.create_topic(&self.topic_router, 1, true).await?;
+        let stream_consumer = transport.subscribe(
             &self.client_id,
-            &self.brokers,
             &self.routed_consumer_group,
-            DefaultConsumerContext,
+            &self.topic_router,
+            Arc::new(|_, _| {}),
         )?;
-        stream_consumer.subscribe(&[self.topic_router.as_str()])?;
 
-        let brokers = self.brokers.clone();
         let topic_router = self.topic_router.clone();
         tokio::task::spawn(async move {
-            publish_route_info(&brokers, &topic_router, recv).await;
+            publish_route_info(transport.as_ref(), &topic_router, recv).await;
         });
-        materialize_route_loop(&self.client_id, &self.route_table, stream_consumer).await
+        materialize_route_loop(
+            &self.client_id,
+            &self.route_table,
+            &self.state,
+            &self.metrics,
+            stream_consumer.as_ref(),
+        )
+        .await
     }
 }
 
-async fn create_route_topic_if_not_exists(brokers: &str, topic: &str) -> anyhow::Result<()> {
-    let admin: AdminClient<DefaultClientContext> = ClientConfig::new()
-        .set("bootstrap.servers", brokers)
-        .set("session.timeout.ms", "6000")
-        .create()?;
+impl KafenceHandle {
+    /// Ready once every partition of the topic has a known owner.
+    fn is_ready(&self) -> bool {
+        let route_table = self.kafence.route_table.read().unwrap();
+        (0..self.kafence.partitions).all(|partition| {
+            route_table.contains_key(&format!("{}:{}", self.kafence.topic_router, partition))
+        })
+    }
 
-    //TODO:Make replication factor configurable
-    let new_topic =
-        NewTopic::new(topic, 1, TopicReplication::Fixed(1)).set("cleanup.policy", "compact");
-    let admin_options = AdminOptions::new().operation_timeout(Some(Duration::from_secs(10)));
+    async fn ready(&self) -> Result<()> {
+        while !self.is_ready() {
+            if let Some((name, _)) = self.tasks.iter().find(|(_, task)| task.is_finished()) {
+                anyhow::bail!("{name} task finished before kafence was ready");
+            }
+            tokio::time::sleep(READY_POLL_INTERVAL).await;
+        }
+        Ok(())
+    }
 
-    for result in admin.create_topics(&[new_topic], &admin_options).await? {
-        match result {
-            Ok(name) => {
-                println!("Created topic name {}", name)
+    /// Reject new writes, wait for the writes in flight to be materialized, then stop the streams.
+    /// The streams commit their offsets, the routes of the owned partitions are removed, and the
+    /// state store is closed.
+    async fn shutdown(self) -> Result<()> {
+        let kafence = self.kafence;
+        info!(client_id = %kafence.client_id, "draining writes");
+        kafence.state.send_replace(KafenceState::Draining);
+        let drained = tokio::time::timeout(SHUTDOWN_DRAIN_TIMEOUT, async {
+            while !kafence.materializer_ack.read().unwrap().is_empty() {
+                tokio::time::sleep(READY_POLL_INTERVAL).await;
             }
-            Err((_, RDKafkaErrorCode::TopicAlreadyExists)) => {}
-            Err((name, code)) => {
-                anyhow::bail!("error creating topic {} with error {}", name, code)
+        })
+        .await;
+        if drained.is_err() {
+            warn!(
+                client_id = %kafence.client_id,
+                timeout = ?SHUTDOWN_DRAIN_TIMEOUT,
+                "writes not materialized before shutdown, dropping them"
+            );
+        }
+        kafence.state.send_replace(KafenceState::Stopped);
+
+        let mut stopped = Ok(());
+        for (name, task) in self.tasks {
+            let result = match task.await {
+                Ok(result) => result,
+                Err(e) => Err(e.into()),
+            };
+            if let Err(e) = result {
+                warn!(
+                    client_id = %kafence.client_id,
+                    task = name,
+                    error = %e,
+                    "stream task failed"
+                );
+                if stopped.is_ok() {
+                    stopped = Err(e.context(format!("{name} task failed")));
+                }
             }
         }
+        // The materializer acknowledged everything it consumed before stopping, the rest is dropped.
+        kafence.materializer_ack.write().unwrap().clear();
+        if let Some(state_store) = kafence.state_store.get() {
+            state_store.close()?;
+        }
+        info!(client_id = %kafence.client_id, "stopped");
+        stopped
     }
-    Ok(())
 }
 
-type KafenceStreamConsumer = StreamConsumer<KafenceConsumerContext>;
-
-fn create_consumer<C>(
-    client_id: &str,
-    brokers: &str,
-    group_id: &str,
-    context: C,
-) -> Result<StreamConsumer<C>, KafkaError>
-where
-    C: ConsumerContext + 'static,
-{
-    ClientConfig::new()
-        .set("bootstrap.servers", brokers)
-        .set("group.id", group_id)
-        .set("client.id", client_id)
-        .set("enable.auto.commit", "false")
-        .set("auto.offset.reset", "earliest")
-        .set("session.timeout.ms", "6000")
-        .create_with_context(context)
-        .map_err(Into::into)
-}
-
-fn open_rocksdb(path: &str) -> Result<DB, rocksdb::Error> {
-    let mut options = Options::default();
-    options.create_if_missing(true);
-    Ok(DB::open(&options, path)?)
-}
-
-async fn materialize_loop(
-    client_id: &str,
-    stream_consumer: &KafenceStreamConsumer,
-    rocks_db: &DB,
-    materializer_ack: &MaterializerAck,
-) -> Result<()> {
-    loop {
-        let message = stream_consumer.recv().await?;
-        materialize_rocksdb(client_id, &message, rocks_db, materializer_ack)?;
-        stream_consumer.commit_message(&message, CommitMode::Async)?;
+/// Seek the assigned partitions to the offsets stored in the state store, and return the end
+/// offsets of the partitions that are behind.
+fn restore_positions(
+    topic: &str,
+    stream_consumer: &dyn TransportConsumer,
+    state_store: &dyn StateStore,
+    assignment: &PartitionAssignment,
+) -> Result<HashMap<i32, i64>> {
+    let mut pending = HashMap::new();
+    for partition in &assignment.partitions {
+        let offset = stored_offset(state_store, topic, *partition)?.unwrap_or(0);
+        if let Err(e) = stream_consumer.seek(*partition, offset) {
+            warn!(
+                partition = *partition,
+                error = %e,
+                "restore seek failed, consuming from the committed offset"
+            );
+        }
+        let end = stream_consumer.end_offset(*partition)?;
+        if offset < end {
+            pending.insert(*partition, end);
+        }
     }
+    Ok(pending)
 }
 
 async fn materialize_route_loop(
     client_id: &str,
-    route_table: &Arc<RwLock<HashMap<String, String>>>,
-    stream_consumer: StreamConsumer,
+    route_table: &RouteTable,
+    state: &StreamState,
+    metrics: &KafenceMetrics,
+    stream_consumer: &dyn TransportConsumer,
 ) -> Result<()> {
+    let mut state = state.subscribe();
     loop {
-        let message = stream_consumer.recv().await?;
-        materialize_route_table(client_id, &message, route_table)?;
-        stream_consumer.commit_message(&message, CommitMode::Async)?;
+        tokio::select! {
+            biased;
+            _ = state.wait_for(|state| *state == KafenceState::Stopped) => return Ok(()),
+            message = stream_consumer.recv() => {
+                let message = message?;
+                materialize_route_table(client_id, &message, route_table)?;
+                metrics
+                    .route_table_size
+                    .set(route_table.read().unwrap().len() as i64);
+                stream_consumer.commit(&message)?;
+            }
+        }
     }
 }
 
-fn materialize_rocksdb(
+fn materialize_state_store(
     client_id: &str,
-    message: &BorrowedMessage<'_>,
-    rocks_db: &DB,
-    materializer_ack: &MaterializerAck,
+    message: &TransportRecord,
+    state_store: &dyn StateStore,
+    materializer_lock: &Mutex<()>,
+    materializer_ack: Option<&MaterializerAck>,
 ) -> Result<()> {
     let key = materialized_key(message);
-    let materializer_key = String::from_utf8_lossy(&key).into_owned();
 
-    match message.payload() {
-        Some(value) => {
-            println!(
-                "Materialized message client_id={} topic={} partition={} offset={} key={:?} value={:?}",
-                client_id,
-                message.topic(),
-                message.partition(),
-                message.offset(),
-                String::from_utf8_lossy(&key),
-                String::from_utf8_lossy(value)
-            );
-            rocks_db.put(&key, value)?
-        }
-        None => {
-            println!(
-                "Deleted tombstone topic={} partition={} offset={} key={:?}",
-                message.topic(),
-                message.partition(),
-                message.offset(),
-                String::from_utf8_lossy(&key)
-            );
-            rocks_db.delete(&key)?
+    let _materializing = materializer_lock.lock().unwrap();
+    // Records already restored, or materialized by the standby stream, are not applied twice.
+    let materialized = stored_offset(state_store, &message.topic, message.partition)?
+        .is_some_and(|offset| message.offset < offset);
+    if !materialized {
+        match message.payload.as_deref() {
+            // Produced to the topic without kafence, it would overwrite an offset or a version.
+            _ if is_reserved_key(&key) => {
+                warn!(
+                    client_id = %client_id,
+                    topic = %message.topic,
+                    partition = message.partition,
+                    offset = message.offset,
+                    key = %String::from_utf8_lossy(&key),
+                    "skipped record with a reserved key"
+                );
+            }
+            Some(value) => {
+                debug!(
+                    client_id = %client_id,
+                    topic = %message.topic,
+                    partition = message.partition,
+                    offset = message.offset,
+                    key = %String::from_utf8_lossy(&key),
+                    value_bytes = value.len(),
+                    "materialized record"
+                );
+                state_store.put(&key, value)?;
+                store_version(state_store, &key, message.offset as u64 + 1)?
+            }
+            None => {
+                debug!(
+                    client_id = %client_id,
+                    topic = %message.topic,
+                    partition = message.partition,
+                    offset = message.offset,
+                    key = %String::from_utf8_lossy(&key),
+                    "materialized tombstone"
+                );
+                state_store.delete(&key)?;
+                delete_version(state_store, &key)?
+            }
         }
+        store_offset(
+            state_store,
+            &message.topic,
+            message.partition,
+            message.offset + 1,
+        )?;
+    }
+    if let Some(materializer_ack) = materializer_ack {
+        acknowledge_materialized_key(materializer_ack, &key);
     }
-    acknowledge_materialized_key(materializer_ack, &materializer_key);
     Ok(())
 }
 
 fn materialize_route_table(
     client_id: &str,
-    message: &BorrowedMessage<'_>,
-    route_table: &Arc<RwLock<HashMap<String, String>>>,
+    message: &TransportRecord,
+    route_table: &RouteTable,
 ) -> Result<()> {
     let key = materialized_key(message);
 
-    match message.payload() {
+    match message.payload.as_deref() {
         Some(value) => {
-            println!(
-                "Materialized partition owner client_id={} topic={} partition={} offset={} key={:?} value={:?}",
-                client_id,
-                message.topic(),
-                message.partition(),
-                message.offset(),
-                String::from_utf8_lossy(&key),
-                String::from_utf8_lossy(value)
+            info!(
+                client_id = %client_id,
+                topic = %message.topic,
+                partition = message.partition,
+                offset = message.offset,
+                key = %String::from_utf8_lossy(&key),
+                route = %String::from_utf8_lossy(value),
+                "materialized route"
             );
             let mut route_table = route_table.write().unwrap();
             let key = String::from_utf8_lossy(&key).to_string();
-            let value = String::from_utf8_lossy(value).to_string();
-            route_table.insert(key, value);
+            let route =
+                serde_json::from_slice::<RouteEntry>(value).unwrap_or_else(|_| RouteEntry {
+                    host: String::from_utf8_lossy(value).to_string(),
+                    epoch: 0,
+                });
+            match route_table.get(&key) {
+                Some(current) if current.epoch > route.epoch => {
+                    warn!(
+                        key = %key,
+                        host = %route.host,
+                        epoch = route.epoch,
+                        current_epoch = current.epoch,
+                        "fenced stale route"
+                    );
+                }
+                _ if route.host.is_empty() => {
+                    route_table.remove(&key);
+                }
+                _ => {
+                    route_table.insert(key, route);
+                }
+            }
         }
         None => {
-            println!(
-                "Deleted tombstone topic={} partition={} offset={} key={:?}",
-                message.topic(),
-                message.partition(),
-                message.offset(),
-                String::from_utf8_lossy(&key)
+            info!(
+                client_id = %client_id,
+                topic = %message.topic,
+                partition = message.partition,
+                offset = message.offset,
+                key = %String::from_utf8_lossy(&key),
+                "removed route"
             );
             let key = String::from_utf8_lossy(&key).to_string();
             route_table.write().unwrap().remove(&key);
@@ -471,20 +1001,30 @@ fn materialize_route_table(
     Ok(())
 }
 
-fn materialized_key(message: &BorrowedMessage<'_>) -> Vec<u8> {
-    match message.key() {
+fn materialized_key(message: &TransportRecord) -> Vec<u8> {
+    match message.key.as_deref() {
         Some(key) => key.to_vec(),
-        None => format!(
-            "{}:{}:{}",
-            message.topic(),
-            message.partition(),
-            message.offset()
-        )
-        .into_bytes(),
+        None => format!("{}:{}:{}", message.topic, message.partition, message.offset).into_bytes(),
     }
 }
 
-fn acknowledge_materialized_key(acknowledge: &MaterializerAck, key: &str) {
+/// Writers waiting for records of revoked partitions are released, since the next owner
+/// materializes those records. Their writes fail with `not_owner` instead of waiting until shutdown.
+fn release_revoked_acks(
+    acknowledge: &MaterializerAck,
+    revoked: &HashSet<i32>,
+    partition_count: i32,
+) {
+    if revoked.is_empty() {
+        return;
+    }
+    acknowledge
+        .write()
+        .unwrap()
+        .retain(|key, _| !revoked.contains(&partition_for_key(key, partition_count)));
+}
+
+fn acknowledge_materialized_key(acknowledge: &MaterializerAck, key: &[u8]) {
     if let Some(waiters) = acknowledge.write().unwrap().remove(key) {
         for waiter in waiters {
             let _ = waiter.send(());
@@ -501,6 +1041,17 @@ enum StrongConsistencyPath {
     Proxied(String),
 }
 
+/// Checked by the owner of the key against its state store before producing the write, while it holds
+/// the key, so no other write of the key can be produced in between.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum WriteCondition {
+    Always,
+    /// The record of the key has this version, or the key has no record with `None`.
+    Version(Option<u64>),
+    /// The record of the key has this encoded value.
+    Value(Vec<u8>),
+}
+
 trait KafenceProducerContract<K, V> {
     async fn strong_consistency(
         &self,
@@ -516,102 +1067,398 @@ trait KafenceProducerContract<K, V> {
         key: &K,
         value: &V,
     ) -> Result<()>;
+
+    /// Write only if the record of the key has `expected_version`, or if the key has no record with
+    /// `None`. Fails with `condition_failed` otherwise.
+    async fn strong_consistency_if(
+        &self,
+        topic: &str,
+        key: K,
+        expected_version: Option<u64>,
+        value: V,
+    ) -> Result<StrongConsistencyPath>;
+
+    /// Write only if the record of the key has the `expected` value, or if the key has no record with
+    /// `None`. Fails with `condition_failed` otherwise.
+    async fn compare_and_set(
+        &self,
+        topic: &str,
+        key: K,
+        expected: Option<V>,
+        value: V,
+    ) -> Result<StrongConsistencyPath>;
 }
 
-impl<K: ToBytes + Send + Sync + Clone + 'static, V: ToBytes + Send> KafenceProducerContract<K, V>
-    for KafenceProducer
-{
+impl<K: Send + Sync, V: Send + Sync> KafenceProducerContract<K, V> for KafenceProducer<K, V> {
     async fn strong_consistency(
         &self,
         topic: &str,
         key: K,
         value: V,
     ) -> Result<StrongConsistencyPath> {
-        let partition = partition_for_key(key.to_bytes(), self.partitions);
-        let route_key = format!("{}:{}", self.topic_router, partition);
+        let key = self.key_codec.encode(&key)?;
+        let value = self.value_codec.encode(&value)?;
+        self.strong_consistency_encoded(topic, &key, &value, &WriteCondition::Always)
+            .await
+    }
 
-        let key_string = String::from_utf8_lossy(key.to_bytes()).into_owned();
-        let value_string = String::from_utf8_lossy(value.to_bytes()).into_owned();
+    async fn local_persistance(
+        &self,
+        topic: &str,
+        partition: i32,
+        key: &K,
+        value: &V,
+    ) -> Result<()> {
+        let key = self.key_codec.encode(key)?;
+        let value = self.value_codec.encode(value)?;
+        self.local_persistance_encoded(topic, partition, &key, &value, &WriteCondition::Always)
+            .await
+    }
 
-        let target_host = self.route_table.read().unwrap().get(&route_key).cloned();
+    async fn strong_consistency_if(
+        &self,
+        topic: &str,
+        key: K,
+        expected_version: Option<u64>,
+        value: V,
+    ) -> Result<StrongConsistencyPath> {
+        let key = self.key_codec.encode(&key)?;
+        let value = self.value_codec.encode(&value)?;
+        let condition = WriteCondition::Version(expected_version);
+        self.strong_consistency_encoded(topic, &key, &value, &condition)
+            .await
+    }
 
-        match target_host {
-            Some(target_host) if target_host == self.service_url => {
-                self.local_persistance(topic, partition, &key, &value)
-                    .await?;
-                Ok(StrongConsistencyPath::Local)
-            }
-            Some(target_host) => {
-                proxy_strong_consistency(&target_host, key_string, value_string).await?;
-                Ok(StrongConsistencyPath::Proxied(target_host))
-            }
-            None => {
-                anyhow::bail!("route not ready for {route_key}");
+    async fn compare_and_set(
+        &self,
+        topic: &str,
+        key: K,
+        expected: Option<V>,
+        value: V,
+    ) -> Result<StrongConsistencyPath> {
+        let key = self.key_codec.encode(&key)?;
+        let value = self.value_codec.encode(&value)?;
+        let condition = match expected {
+            Some(expected) => WriteCondition::Value(self.value_codec.encode(&expected)?),
+            None => WriteCondition::Version(None),
+        };
+        self.strong_consistency_encoded(topic, &key, &value, &condition)
+            .await
+    }
+}
+
+impl<K, V> KafenceProducer<K, V> {
+    async fn strong_consistency_encoded(
+        &self,
+        topic: &str,
+        key: &[u8],
+        value: &[u8],
+        condition: &WriteCondition,
+    ) -> Result<StrongConsistencyPath> {
+        if *self.state.borrow() != KafenceState::Running {
+            return Err(
+                KafenceError::new(KafenceErrorCode::Unavailable, "kafence is shut down").into(),
+            );
+        }
+        check_record_key(key)?;
+        let partition = partition_for_key(key, self.partitions);
+
+        let started = Instant::now();
+        let mut attempt = 1;
+        loop {
+            let result = match route_owner(&self.route_table, &self.topic_router, partition) {
+                Ok(route) if route.host == self.service_url => {
+                    match owned_epoch(
+                        &self.route_table,
+                        &self.assignment,
+                        &self.topic_router,
+                        &self.service_url,
+                        partition,
+                        None,
+                    ) {
+                        Ok(_) => self
+                            .local_persistance_encoded(topic, partition, key, value, condition)
+                            .await
+                            .map(|_| StrongConsistencyPath::Local),
+                        Err(e) => Err(e.into()),
+                    }
+                }
+                Ok(route) => proxy_strong_consistency(&route, key, value, condition)
+                    .await
+                    .inspect_err(|e| {
+                        if attempt < ROUTE_ATTEMPTS && is_route_retryable(e) {
+                            self.metrics.proxy_retried("write", e)
+                        } else {
+                            self.metrics.proxy_failed("write", e)
+                        }
+                    })
+                    .map(|_| StrongConsistencyPath::Proxied(route.host)),
+                Err(e) => Err(e.into()),
+            };
+            match result {
+                Err(e) if attempt < ROUTE_ATTEMPTS && is_route_retryable(&e) => {
+                    info!(
+                        key = %String::from_utf8_lossy(key),
+                        partition,
+                        attempt,
+                        attempts = ROUTE_ATTEMPTS,
+                        error = %e,
+                        "route not settled, retrying write"
+                    );
+                    tokio::time::sleep(ROUTE_RETRY_BACKOFF * attempt).await;
+                    attempt += 1;
+                }
+                result => {
+                    if let Ok(path) = &result {
+                        let path = match path {
+                            StrongConsistencyPath::Local => "local",
+                            StrongConsistencyPath::Proxied(_) => "proxied",
+                        };
+                        self.metrics.writes.with_label_values(&[path]).inc();
+                        self.metrics
+                            .write_duration
+                            .with_label_values(&[path])
+                            .observe(started.elapsed().as_secs_f64());
+                    }
+                    return result;
+                }
             }
         }
     }
 
-    async fn local_persistance(
+    async fn local_persistance_encoded(
         &self,
         topic: &str,
         partition: i32,
-        key: &K,
-        value: &V,
+        key: &[u8],
+        value: &[u8],
+        condition: &WriteCondition,
     ) -> Result<()> {
-        let materializer_key = String::from_utf8_lossy(key.to_bytes()).into_owned();
-        let materialized =
-            lock_materialization_key(&self.materializer_ack, materializer_key.clone()).await;
-
-        println!(
-            "Instance for key={:?} owner of partition={:?} persitance locally.",
-            String::from_utf8_lossy(key.to_bytes()),
-            partition
+        check_record_key(key)?;
+        let materialized = lock_materialization_key(&self.materializer_ack, key.to_vec()).await;
+
+        if *self.state.borrow() != KafenceState::Running {
+            // Proxied writers retry against the next owner of the partition.
+            acknowledge_materialized_key(&self.materializer_ack, key);
+            return Err(KafenceError::new(
+                KafenceErrorCode::NotOwner,
+                format!("partition {partition} owner is shutting down"),
+            )
+            .into());
+        }
+
+        // Every previous write of the key went through the lock, so it is already materialized.
+        let checked = local_store(&self.state_store)
+            .and_then(|state_store| check_write_condition(state_store, key, condition));
+        if let Err(e) = checked {
+            acknowledge_materialized_key(&self.materializer_ack, key);
+            return Err(e);
+        }
+
+        debug!(
+            key = %String::from_utf8_lossy(key),
+            partition,
+            "owner of the partition, producing locally"
         );
-        let record = FutureRecord::to(topic)
-            .partition(partition)
-            .key(key)
-            .payload(value);
-
-        match self.producer.send(record, Duration::from_secs(5)).await {
-            Ok(_) => {}
-            Err((e, _)) => {
-                acknowledge_materialized_key(&self.materializer_ack, &materializer_key);
-                anyhow::bail!("record delivery failed: {e}");
-            }
+        let produced = Instant::now();
+        let sent = self
+            .transport
+            .send(topic, Some(partition), key, Some(value))
+            .await;
+
+        if let Err(e) = sent {
+            acknowledge_materialized_key(&self.materializer_ack, key);
+            return Err(e);
         }
 
         materialized.await.map_err(|_| {
-            anyhow::anyhow!("materializer acknowledge dropped for key {materializer_key}")
+            if self
+                .assignment
+                .read()
+                .unwrap()
+                .partitions
+                .contains(&partition)
+            {
+                anyhow::anyhow!(
+                    "materializer acknowledge dropped for key {}",
+                    String::from_utf8_lossy(key)
+                )
+            } else {
+                KafenceError::new(
+                    KafenceErrorCode::NotOwner,
+                    format!("partition {partition} revoked before the write was materialized"),
+                )
+                .into()
+            }
         })?;
+        self.metrics
+            .ack_latency
+            .observe(produced.elapsed().as_secs_f64());
 
         Ok(())
     }
 }
-async fn proxy_strong_consistency(target_host: &str, key: String, value: String) -> Result<()> {
-    println!(
-        "Instance for key={:?} is not owner of partition. Proxy request to {} .",
-        String::from_utf8_lossy(key.to_bytes()),
-        target_host
+
+/// Keys travel base64 encoded in the headers, so binary keys reach the owner unchanged, and values as
+/// the binary body of the request.
+async fn proxy_strong_consistency(
+    route: &RouteEntry,
+    key: &[u8],
+    value: &[u8],
+    condition: &WriteCondition,
+) -> Result<()> {
+    debug!(
+        key = %String::from_utf8_lossy(key),
+        host = %route.host,
+        epoch = route.epoch,
+        "not owner of the partition, proxying write"
     );
-    let request = hyper::Request::builder()
+    let mut request = hyper::Request::builder()
         .method(hyper::Method::POST)
-        .uri(format!("{}/", target_host.trim_end_matches('/')))
-        .header("record-key", key)
-        .header("x-kafence-proxied", "true")
-        .body(hyper::Body::from(value))?;
+        .uri(records_url(&route.host))
+        .header(RECORD_KEY_HEADER, BASE64_STANDARD.encode(key))
+        .header(RECORD_KEY_ENCODING_HEADER, BASE64_KEY_ENCODING)
+        .header(PROXIED_HEADER, "true")
+        .header(ROUTE_EPOCH_HEADER, route.epoch)
+        .header(hyper::header::CONTENT_TYPE, "application/octet-stream");
+    request = match condition {
+        WriteCondition::Always => request,
+        WriteCondition::Version(Some(version)) => request.header(IF_VERSION_HEADER, *version),
+        WriteCondition::Version(None) => request.header(IF_VERSION_HEADER, ABSENT_VERSION),
+        WriteCondition::Value(expected) => {
+            request.header(IF_VALUE_HEADER, BASE64_STANDARD.encode(expected))
+        }
+    };
+    let request = request.body(hyper::Body::from(value.to_vec()))?;
 
     let response = hyper::Client::new().request(request).await?;
 
     if !response.status().is_success() {
-        anyhow::bail!("proxy failed with status {}", response.status());
+        return Err(KafenceError::from_response(response).await.into());
     }
 
     Ok(())
 }
 
+/// Offsets and versions are stored next to the records, so a record cannot have a reserved key.
+fn check_record_key(key: &[u8]) -> Result<(), KafenceError> {
+    if is_reserved_key(key) {
+        return Err(KafenceError::new(
+            KafenceErrorCode::BadRequest,
+            format!(
+                "record key {} uses the reserved kafence prefix",
+                String::from_utf8_lossy(key)
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn check_write_condition(
+    state_store: &dyn StateStore,
+    key: &[u8],
+    condition: &WriteCondition,
+) -> Result<()> {
+    let failed = match condition {
+        WriteCondition::Always => None,
+        WriteCondition::Version(expected) => {
+            let current = current_version(state_store, key)?;
+            (current != *expected).then(|| match current {
+                Some(version) => format!("current version is {version}"),
+                None => "key has no record".to_string(),
+            })
+        }
+        WriteCondition::Value(expected) => {
+            let current = state_store.get(key)?;
+            (current.as_ref() != Some(expected)).then(|| match current {
+                Some(_) => "current value is different".to_string(),
+                None => "key has no record".to_string(),
+            })
+        }
+    };
+    match failed {
+        Some(reason) => Err(KafenceError::new(
+            KafenceErrorCode::ConditionFailed,
+            format!(
+                "condition failed for key {:?}, {}",
+                String::from_utf8_lossy(key),
+                reason
+            ),
+        )
+        .into()),
+        None => Ok(()),
+    }
+}
+
+/// Version of the record of the key, `0` for a record materialized before versions were stored.
+fn current_version(state_store: &dyn StateStore, key: &[u8]) -> Result<Option<u64>> {
+    match stored_version(state_store, key)? {
+        Some(version) => Ok(Some(version)),
+        None => Ok(state_store.get(key)?.map(|_| 0)),
+    }
+}
+
+fn route_owner(
+    route_table: &RouteTable,
+    topic_router: &str,
+    partition: i32,
+) -> Result<RouteEntry, KafenceError> {
+    let route_key = format!("{}:{}", topic_router, partition);
+    route_table
+        .read()
+        .unwrap()
+        .get(&route_key)
+        .cloned()
+        .ok_or_else(|| {
+            KafenceError::new(
+                KafenceErrorCode::RouteNotReady,
+                format!("route not ready for {route_key}"),
+            )
+        })
+}
+
+/// Epoch this instance owns the partition with. The partition must be in the last assignment of the
+/// instance, and no other instance can hold a route with a newer epoch, or a newer epoch than
+/// `min_epoch` when the caller knows one.
+fn owned_epoch(
+    route_table: &RouteTable,
+    assignment: &LocalAssignment,
+    topic_router: &str,
+    service_url: &str,
+    partition: i32,
+    min_epoch: Option<u64>,
+) -> Result<u64, KafenceError> {
+    let assignment = assignment.read().unwrap();
+    let newer_owner = route_owner(route_table, topic_router, partition)
+        .ok()
+        .filter(|route| route.host != service_url && route.epoch > assignment.epoch);
+    let stale = min_epoch.is_some_and(|epoch| epoch > assignment.epoch);
+
+    if !assignment.partitions.contains(&partition) || newer_owner.is_some() || stale {
+        return Err(KafenceError::new(
+            KafenceErrorCode::NotOwner,
+            format!(
+                "{} does not own partition {} with epoch {}",
+                service_url, partition, assignment.epoch
+            ),
+        ));
+    }
+    Ok(assignment.epoch)
+}
+
+fn is_route_retryable(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<KafenceError>(),
+        Some(KafenceError {
+            code: KafenceErrorCode::NotOwner | KafenceErrorCode::RouteNotReady,
+            ..
+        })
+    )
+}
+
 async fn lock_materialization_key(
     acknowledge: &MaterializerAck,
-    key: String,
+    key: Vec<u8>,
 ) -> oneshot::Receiver<()> {
     loop {
         let wait_previous = {
@@ -633,8 +1480,541 @@ async fn lock_materialization_key(
     }
 }
 
+// Kafka Query
+// -----------
+
+struct KafenceQuery<K = String, V = Vec<u8>> {
+    service_url: String,
+    topic_router: String,
+    partitions: i32,
+    route_table: RouteTable,
+    assignment: LocalAssignment,
+    state_store: LocalStateStore,
+    metrics: Arc<KafenceMetrics>,
+    key_codec: Arc<dyn Codec<K>>,
+    value_codec: Arc<dyn Codec<V>>,
+}
+
+/// Reads decode the keys and values of the store with the codecs of the query. A prefix is encoded
+/// with the key codec too, so prefix scans only make sense with codecs that keep the prefix of a key,
+/// such as [BytesCodec].
+trait KafenceQueryContract<K, V> {
+    async fn get(&self, key: K) -> Result<Option<V>>;
+
+    /// Value of the key with the version of its record, to write it back with
+    /// `strong_consistency_if`.
+    async fn get_with_version(&self, key: K) -> Result<Option<(V, u64)>>;
+
+    async fn multi_get(&self, keys: Vec<K>) -> Result<Vec<Option<V>>>;
+
+    async fn prefix_scan(&self, prefix: K) -> Result<Vec<(K, V)>>;
+
+    fn local_get(&self, key: &K) -> Result<Option<V>>;
+
+    fn local_prefix_scan(&self, prefix: &K) -> Result<Vec<(K, V)>>;
+}
+
+impl<K, V> KafenceQuery<K, V> {
+    fn owned_epoch(&self, partition: i32, min_epoch: Option<u64>) -> Result<u64, KafenceError> {
+        owned_epoch(
+            &self.route_table,
+            &self.assignment,
+            &self.topic_router,
+            &self.service_url,
+            partition,
+            min_epoch,
+        )
+    }
+
+    fn owns_partition(&self, partition: i32) -> bool {
+        self.owned_epoch(partition, None).is_ok()
+    }
+
+    fn decode_value(&self, value: Option<VersionedValue>) -> Result<Option<(V, u64)>> {
+        value
+            .map(|(value, version)| Ok((self.value_codec.decode(&value)?, version)))
+            .transpose()
+    }
+
+    fn decode_entries(&self, entries: Vec<KafenceEntry>) -> Result<Vec<(K, V)>> {
+        entries
+            .into_iter()
+            .map(|(key, value)| {
+                Ok((
+                    self.key_codec.decode(&key)?,
+                    self.value_codec.decode(&value)?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Reads of a partition changing hands are retried like writes, until its route settles.
+    async fn get_encoded(&self, key: &[u8]) -> Result<Option<VersionedValue>> {
+        let mut attempt = 1;
+        loop {
+            match self.routed_get_encoded(key, attempt).await {
+                Err(e) if attempt < ROUTE_ATTEMPTS && is_route_retryable(&e) => {
+                    info!(
+                        key = %String::from_utf8_lossy(key),
+                        attempt,
+                        attempts = ROUTE_ATTEMPTS,
+                        error = %e,
+                        "route not settled, retrying read"
+                    );
+                    tokio::time::sleep(ROUTE_RETRY_BACKOFF * attempt).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn routed_get_encoded(&self, key: &[u8], attempt: u32) -> Result<Option<VersionedValue>> {
+        let partition = partition_for_key(key, self.partitions);
+        let route = route_owner(&self.route_table, &self.topic_router, partition)?;
+
+        if route.host == self.service_url {
+            self.local_get_encoded(key)
+        } else {
+            proxy_get(&route, key).await.inspect_err(|e| {
+                if attempt < ROUTE_ATTEMPTS && is_route_retryable(e) {
+                    self.metrics.proxy_retried("get", e)
+                } else {
+                    self.metrics.proxy_failed("get", e)
+                }
+            })
+        }
+    }
+
+    /// Values of the keys, in the same order. Keys are grouped by the owner of their partition, and
+    /// every other owner receives one request with all of its keys, sent concurrently. Keys whose
+    /// route is not settled, or rejected by their owner with `not_owner`, are grouped again and
+    /// retried like writes, without reading the other keys again.
+    async fn multi_get_encoded(&self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>> {
+        let mut values = vec![None; keys.len()];
+        let mut pending = (0..keys.len()).collect::<Vec<_>>();
+        let mut attempt = 1;
+        loop {
+            let retried = |e: &anyhow::Error| attempt < ROUTE_ATTEMPTS && is_route_retryable(e);
+            let mut rejected = vec![];
+            let mut owners = HashMap::<String, Vec<usize>>::new();
+            for index in pending {
+                let partition = partition_for_key(&keys[index], self.partitions);
+                match route_owner(&self.route_table, &self.topic_router, partition) {
+                    Ok(route) => owners.entry(route.host).or_default().push(index),
+                    Err(e) => {
+                        let e: anyhow::Error = e.into();
+                        if !retried(&e) {
+                            return Err(e);
+                        }
+                        rejected.push(index);
+                    }
+                }
+            }
+
+            let mut proxied = JoinSet::new();
+            for (owner, indexes) in owners {
+                if owner == self.service_url {
+                    for index in indexes {
+                        values[index] = self
+                            .local_get_encoded(&keys[index])?
+                            .map(|(value, _)| value);
+                    }
+                } else {
+                    let owner_keys = indexes.iter().map(|&index| keys[index].clone()).collect();
+                    proxied.spawn(async move {
+                        let owner_values = proxy_multi_get(&owner, owner_keys).await;
+                        (indexes, owner_values)
+                    });
+                }
+            }
+            while let Some(joined) = proxied.join_next().await {
+                let (indexes, owner_values) = joined?;
+                match owner_values {
+                    Ok(owner_values) => {
+                        for (index, value) in indexes.into_iter().zip(owner_values) {
+                            values[index] = value;
+                        }
+                    }
+                    Err(e) if retried(&e) => {
+                        self.metrics.proxy_retried("multi_get", &e);
+                        rejected.extend(indexes);
+                    }
+                    Err(e) => {
+                        self.metrics.proxy_failed("multi_get", &e);
+                        return Err(e);
+                    }
+                }
+            }
+
+            if rejected.is_empty() {
+                return Ok(values);
+            }
+            info!(
+                keys = rejected.len(),
+                attempt,
+                attempts = ROUTE_ATTEMPTS,
+                "route not settled, retrying multi get"
+            );
+            tokio::time::sleep(ROUTE_RETRY_BACKOFF * attempt).await;
+            attempt += 1;
+            pending = rejected;
+        }
+    }
+
+    /// Values of keys this instance owns, for another instance that sent all the keys it routes here.
+    /// A key of a partition this instance does not own fails with `not_owner`, instead of reading
+    /// as missing while the partition changes hands.
+    fn owned_multi_get_encoded(&self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>> {
+        keys.iter()
+            .map(|key| {
+                self.owned_epoch(partition_for_key(key, self.partitions), None)?;
+                Ok(self.local_get_encoded(key)?.map(|(value, _)| value))
+            })
+            .collect()
+    }
+
+    /// Entries of every partition, retried like writes while the routes are not settled.
+    async fn prefix_scan_encoded(&self, prefix: &[u8]) -> Result<Vec<KafenceEntry>> {
+        let mut attempt = 1;
+        loop {
+            match self.routed_prefix_scan_encoded(prefix, attempt).await {
+                Err(e) if attempt < ROUTE_ATTEMPTS && is_route_retryable(&e) => {
+                    info!(
+                        prefix = %String::from_utf8_lossy(prefix),
+                        attempt,
+                        attempts = ROUTE_ATTEMPTS,
+                        error = %e,
+                        "route not settled, retrying scan"
+                    );
+                    tokio::time::sleep(ROUTE_RETRY_BACKOFF * attempt).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn routed_prefix_scan_encoded(
+        &self,
+        prefix: &[u8],
+        attempt: u32,
+    ) -> Result<Vec<KafenceEntry>> {
+        let mut owners = HashMap::<String, Vec<RoutedPartition>>::new();
+        for partition in 0..self.partitions {
+            let route = route_owner(&self.route_table, &self.topic_router, partition)?;
+            owners
+                .entry(route.host)
+                .or_default()
+                .push((partition, route.epoch));
+        }
+
+        let mut entries = vec![];
+        for (owner, partitions) in owners {
+            if owner == self.service_url {
+                entries.extend(self.owned_prefix_scan_encoded(prefix, &partitions)?);
+            } else {
+                entries.extend(
+                    proxy_prefix_scan(&owner, prefix, &partitions)
+                        .await
+                        .inspect_err(|e| {
+                            if attempt < ROUTE_ATTEMPTS && is_route_retryable(e) {
+                                self.metrics.proxy_retried("scan", e)
+                            } else {
+                                self.metrics.proxy_failed("scan", e)
+                            }
+                        })?,
+                );
+            }
+        }
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(entries)
+    }
+
+    /// Entries of the partitions a caller routes to this instance, with the epochs of its routes.
+    /// If this instance does not own all of them, the scan fails with `not_owner`, so no partition
+    /// is silently missing from the result while it changes hands.
+    fn owned_prefix_scan_encoded(
+        &self,
+        prefix: &[u8],
+        partitions: &[RoutedPartition],
+    ) -> Result<Vec<KafenceEntry>> {
+        for &(partition, epoch) in partitions {
+            self.owned_epoch(partition, Some(epoch))?;
+        }
+        let partitions = partitions
+            .iter()
+            .map(|&(partition, _)| partition)
+            .collect::<HashSet<_>>();
+        let entries = local_store(&self.state_store)?.scan(prefix)?;
+        Ok(entries
+            .into_iter()
+            .filter(|(key, _)| {
+                !is_reserved_key(key)
+                    && partitions.contains(&partition_for_key(key, self.partitions))
+            })
+            .collect())
+    }
+
+    fn local_get_encoded(&self, key: &[u8]) -> Result<Option<VersionedValue>> {
+        if is_reserved_key(key) {
+            return Ok(None);
+        }
+        let state_store = local_store(&self.state_store)?;
+        // The materializer stores the value before the version, so reading them in the opposite order
+        // never pairs a new value with an old version.
+        let version = stored_version(state_store, key)?;
+        let value = state_store.get(key)?;
+        Ok(value.map(|value| (value, version.unwrap_or(0))))
+    }
+
+    fn local_prefix_scan_encoded(&self, prefix: &[u8]) -> Result<Vec<KafenceEntry>> {
+        let entries = local_store(&self.state_store)?.scan(prefix)?;
+        // Records of partitions this instance no longer owns are served by the new owner.
+        Ok(entries
+            .into_iter()
+            .filter(|(key, _)| {
+                !is_reserved_key(key)
+                    && self.owns_partition(partition_for_key(key, self.partitions))
+            })
+            .collect())
+    }
+}
+
+impl<K: Send + Sync, V: Send + Sync> KafenceQueryContract<K, V> for KafenceQuery<K, V> {
+    async fn get(&self, key: K) -> Result<Option<V>> {
+        Ok(self.get_with_version(key).await?.map(|(value, _)| value))
+    }
+
+    async fn get_with_version(&self, key: K) -> Result<Option<(V, u64)>> {
+        let key = self.key_codec.encode(&key)?;
+        self.decode_value(self.get_encoded(&key).await?)
+    }
+
+    async fn multi_get(&self, keys: Vec<K>) -> Result<Vec<Option<V>>> {
+        let keys = keys
+            .iter()
+            .map(|key| self.key_codec.encode(key))
+            .collect::<Result<Vec<_>>>()?;
+        self.multi_get_encoded(&keys)
+            .await?
+            .into_iter()
+            .map(|value| {
+                value
+                    .map(|value| self.value_codec.decode(&value))
+                    .transpose()
+            })
+            .collect()
+    }
+
+    async fn prefix_scan(&self, prefix: K) -> Result<Vec<(K, V)>> {
+        let prefix = self.key_codec.encode(&prefix)?;
+        self.decode_entries(self.prefix_scan_encoded(&prefix).await?)
+    }
+
+    fn local_get(&self, key: &K) -> Result<Option<V>> {
+        let key = self.key_codec.encode(key)?;
+        Ok(self
+            .decode_value(self.local_get_encoded(&key)?)?
+            .map(|(value, _)| value))
+    }
+
+    fn local_prefix_scan(&self, prefix: &K) -> Result<Vec<(K, V)>> {
+        let prefix = self.key_codec.encode(prefix)?;
+        self.decode_entries(self.local_prefix_scan_encoded(&prefix)?)
+    }
+}
+
+fn local_store(state_store: &OnceLock<Box<dyn StateStore>>) -> Result<&dyn StateStore> {
+    state_store.get().map(Box::as_ref).ok_or_else(|| {
+        KafenceError::new(
+            KafenceErrorCode::StoreNotReady,
+            "state store not ready, stream must be started first",
+        )
+        .into()
+    })
+}
+
+async fn proxy_get(route: &RouteEntry, key: &[u8]) -> Result<Option<VersionedValue>> {
+    debug!(
+        key = %String::from_utf8_lossy(key),
+        host = %route.host,
+        "not owner of the partition, proxying read"
+    );
+    let request = hyper::Request::builder()
+        .method(hyper::Method::GET)
+        .uri(records_url(&route.host))
+        .header(RECORD_KEY_HEADER, BASE64_STANDARD.encode(key))
+        .header(RECORD_KEY_ENCODING_HEADER, BASE64_KEY_ENCODING)
+        .header(PROXIED_HEADER, "true")
+        .header(ROUTE_EPOCH_HEADER, route.epoch)
+        .body(hyper::Body::empty())?;
+
+    let response = hyper::Client::new().request(request).await?;
+
+    match response.status() {
+        hyper::StatusCode::NOT_FOUND => Ok(None),
+        status if status.is_success() => {
+            let version = response
+                .headers()
+                .get(VERSION_HEADER)
+                .and_then(|version| version.to_str().ok())
+                .and_then(|version| version.parse().ok())
+                .unwrap_or_default();
+            let value = hyper::body::to_bytes(response.into_body()).await?.to_vec();
+            Ok(Some((value, version)))
+        }
+        _ => Err(KafenceError::from_response(response).await.into()),
+    }
+}
+
+/// Keys travel as the fields of [encode_fields] in the body, and the owner answers with the entries
+/// of the keys it has.
+async fn proxy_multi_get(target_host: &str, keys: Vec<Vec<u8>>) -> Result<Vec<Option<Vec<u8>>>> {
+    debug!(
+        keys = keys.len(),
+        host = %target_host,
+        "not owner of the partitions, proxying multi get"
+    );
+    let request = hyper::Request::builder()
+        .method(hyper::Method::POST)
+        .uri(format!(
+            "{}{}",
+            target_host.trim_end_matches('/'),
+            MULTI_GET_PATH
+        ))
+        .header(PROXIED_HEADER, "true")
+        .header(hyper::header::CONTENT_TYPE, "application/octet-stream")
+        .body(hyper::Body::from(encode_fields(&keys)))?;
+
+    let response = hyper::Client::new().request(request).await?;
+
+    if !response.status().is_success() {
+        return Err(KafenceError::from_response(response).await.into());
+    }
+
+    let mut entries = decode_entries(&hyper::body::to_bytes(response.into_body()).await?)?
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    Ok(keys.iter().map(|key| entries.remove(key)).collect())
+}
+
+async fn proxy_prefix_scan(
+    target_host: &str,
+    prefix: &[u8],
+    partitions: &[RoutedPartition],
+) -> Result<Vec<KafenceEntry>> {
+    debug!(
+        prefix = %String::from_utf8_lossy(prefix),
+        host = %target_host,
+        partitions = ?partitions,
+        "not owner of every partition, proxying scan"
+    );
+    let request = hyper::Request::builder()
+        .method(hyper::Method::GET)
+        .uri(records_url(target_host))
+        .header(RECORD_PREFIX_HEADER, BASE64_STANDARD.encode(prefix))
+        .header(RECORD_KEY_ENCODING_HEADER, BASE64_KEY_ENCODING)
+        .header(PROXIED_HEADER, "true")
+        .header(
+            ROUTE_PARTITIONS_HEADER,
+            encode_routed_partitions(partitions),
+        )
+        .body(hyper::Body::empty())?;
+
+    let response = hyper::Client::new().request(request).await?;
+
+    if !response.status().is_success() {
+        return Err(KafenceError::from_response(response).await.into());
+    }
+
+    decode_entries(&hyper::body::to_bytes(response.into_body()).await?)
+}
+
+fn records_url(target_host: &str) -> String {
+    format!("{}{}", target_host.trim_end_matches('/'), RECORDS_PATH)
+}
+
+/// Partitions of a scan routed to one owner, as `partition:epoch` pairs separated by commas.
+fn encode_routed_partitions(partitions: &[RoutedPartition]) -> String {
+    partitions
+        .iter()
+        .map(|(partition, epoch)| format!("{partition}:{epoch}"))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn decode_routed_partitions(partitions: &str) -> Result<Vec<RoutedPartition>> {
+    partitions
+        .split(',')
+        .map(|routed| {
+            let (partition, epoch) = routed
+                .split_once(':')
+                .ok_or_else(|| anyhow::anyhow!("invalid routed partition {routed}"))?;
+            Ok((partition.parse()?, epoch.parse()?))
+        })
+        .collect()
+}
+
+/// Scan results travel between instances as `key length | key | value length | value`,
+/// with lengths as big endian u32.
+fn encode_entries(entries: &[KafenceEntry]) -> Vec<u8> {
+    let mut bytes = vec![];
+    for (key, value) in entries {
+        bytes.extend_from_slice(&(key.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(key);
+        bytes.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(value);
+    }
+    bytes
+}
+
+fn decode_entries(mut bytes: &[u8]) -> Result<Vec<KafenceEntry>> {
+    let mut entries = vec![];
+    while !bytes.is_empty() {
+        let key = take_entry_field(&mut bytes)?;
+        let value = take_entry_field(&mut bytes)?;
+        entries.push((key, value));
+    }
+    Ok(entries)
+}
+
+/// Keys of a multi get, as `key length | key` with the same lengths as the entries.
+fn encode_fields(fields: &[Vec<u8>]) -> Vec<u8> {
+    let mut bytes = vec![];
+    for field in fields {
+        bytes.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(field);
+    }
+    bytes
+}
+
+fn decode_fields(mut bytes: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut fields = vec![];
+    while !bytes.is_empty() {
+        fields.push(take_entry_field(&mut bytes)?);
+    }
+    Ok(fields)
+}
+
+fn take_entry_field(bytes: &mut &[u8]) -> Result<Vec<u8>> {
+    if bytes.len() < 4 {
+        anyhow::bail!("invalid scan entries, missing field length");
+    }
+    let (length, rest) = bytes.split_at(4);
+    let length = u32::from_be_bytes(length.try_into()?) as usize;
+    if rest.len() < length {
+        anyhow::bail!("invalid scan entries, field shorter than {length} bytes");
+    }
+    let (field, rest) = rest.split_at(length);
+    *bytes = rest;
+    Ok(field.to_vec())
+}
+
+/// Routes that cannot be delivered after their retries are logged and skipped, so the next
+/// assignments are still published.
 async fn publish_route_info(
-    brokers: &str,
+    transport: &dyn Transport,
     topic_router: &str,
     mut recv: UnboundedReceiver<RouteInfo>,
 ) {
@@ -646,28 +2026,56 @@ async fn publish_route_info(
             .iter()
             .copied()
             .collect::<Vec<_>>();
-        println!("New Route info {:?}", route_info);
-        match ClientConfig::new()
-            .set("bootstrap.servers", brokers)
-            .set("message.timeout.ms", "5000")
-            .create::<FutureProducer>()
-        {
-            Ok(producer) => {
-                for partition in partitions {
-                    let key = format!("{}:{}", topic_router, partition);
-                    let record = FutureRecord::to(topic_router)
-                        .key(&key)
-                        .payload(&route_info.service_host);
-
-                    producer
-                        .send(record, Duration::from_secs(5))
-                        .await
-                        .expect("record must be delivered");
-                }
+        info!(
+            host = %route_info.service_host,
+            epoch = route_info.epoch,
+            partitions = ?partitions,
+            "publishing routes"
+        );
+        for partition in partitions {
+            let key = format!("{}:{}", topic_router, partition);
+            let route = RouteEntry {
+                host: route_info.service_host.clone(),
+                epoch: route_info.epoch,
+            };
+            if let Err(e) = publish_route(transport, topic_router, &key, &route).await {
+                warn!(
+                    key = %key,
+                    host = %route.host,
+                    epoch = route.epoch,
+                    error = %e,
+                    "route not published"
+                );
             }
-            Err(e) => {
-                println!("Error creating Kafka producer. Caused by {}", e);
+        }
+    }
+}
+
+async fn publish_route(
+    transport: &dyn Transport,
+    topic_router: &str,
+    key: &str,
+    route: &RouteEntry,
+) -> Result<()> {
+    let payload = serde_json::to_vec(route)?;
+    let mut attempt = 1;
+    loop {
+        match transport
+            .send(topic_router, None, key.as_bytes(), Some(&payload))
+            .await
+        {
+            Err(e) if attempt < ROUTE_ATTEMPTS => {
+                warn!(
+                    key = %key,
+                    attempt,
+                    attempts = ROUTE_ATTEMPTS,
+                    error = %e,
+                    "route not delivered, retrying"
+                );
+                tokio::time::sleep(ROUTE_RETRY_BACKOFF * attempt).await;
+                attempt += 1;
             }
+            result => return result,
         }
     }
 }
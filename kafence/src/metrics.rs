@@ -0,0 +1,133 @@
+use crate::router::KafenceError;
+use anyhow::Result;
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use std::collections::HashMap;
+
+/// Metrics of one instance, in a registry of its own labelled with the client id, so instances
+/// sharing a process never register the same metric twice.
+pub(crate) struct KafenceMetrics {
+    registry: Registry,
+    /// Successful strongly consistent writes, by `local` or `proxied` path.
+    pub(crate) writes: IntCounterVec,
+    pub(crate) write_duration: HistogramVec,
+    /// From producing a record of a local write to its materialization in the state store.
+    pub(crate) ack_latency: Histogram,
+    /// Records of the partition not materialized yet, by `owner` or `standby` stream.
+    pub(crate) consumer_lag: IntGaugeVec,
+    pub(crate) route_table_size: IntGauge,
+    /// Failed proxied calls, by `write`, `get`, `multi_get` or `scan` operation and by error code.
+    pub(crate) proxy_failures: IntCounterVec,
+    /// Proxied `write`, `get`, `multi_get` or `scan` calls that failed on an unsettled route and were retried.
+    pub(crate) proxy_retries: IntCounterVec,
+}
+
+impl KafenceMetrics {
+    pub(crate) fn new(client_id: &str) -> KafenceMetrics {
+        let labels = HashMap::from([("client_id".to_string(), client_id.to_string())]);
+        let registry =
+            Registry::new_custom(None, Some(labels)).expect("client id must be a valid label");
+        let metrics = KafenceMetrics {
+            writes: IntCounterVec::new(
+                Opts::new("kafence_writes_total", "Strongly consistent writes"),
+                &["path"],
+            )
+            .unwrap(),
+            write_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "kafence_write_duration_seconds",
+                    "Duration of strongly consistent writes, retries included",
+                ),
+                &["path"],
+            )
+            .unwrap(),
+            ack_latency: Histogram::with_opts(HistogramOpts::new(
+                "kafence_ack_latency_seconds",
+                "Latency from producing a record to its materialization",
+            ))
+            .unwrap(),
+            consumer_lag: IntGaugeVec::new(
+                Opts::new(
+                    "kafence_consumer_lag",
+                    "Records of the partition not materialized yet",
+                ),
+                &["stream", "partition"],
+            )
+            .unwrap(),
+            route_table_size: IntGauge::new(
+                "kafence_route_table_size",
+                "Partitions with a known owner in the route table",
+            )
+            .unwrap(),
+            proxy_failures: IntCounterVec::new(
+                Opts::new("kafence_proxy_failures_total", "Failed proxied calls"),
+                &["operation", "code"],
+            )
+            .unwrap(),
+            proxy_retries: IntCounterVec::new(
+                Opts::new(
+                    "kafence_proxy_retries_total",
+                    "Proxied calls retried on an unsettled route",
+                ),
+                &["operation", "code"],
+            )
+            .unwrap(),
+            registry,
+        };
+        metrics.register().expect("metrics must be registered once");
+        metrics
+    }
+
+    fn register(&self) -> Result<()> {
+        self.registry.register(Box::new(self.writes.clone()))?;
+        self.registry
+            .register(Box::new(self.write_duration.clone()))?;
+        self.registry.register(Box::new(self.ack_latency.clone()))?;
+        self.registry
+            .register(Box::new(self.consumer_lag.clone()))?;
+        self.registry
+            .register(Box::new(self.route_table_size.clone()))?;
+        self.registry
+            .register(Box::new(self.proxy_failures.clone()))?;
+        self.registry
+            .register(Box::new(self.proxy_retries.clone()))?;
+        Ok(())
+    }
+
+    /// A proxied call that failed for good, after its retries.
+    pub(crate) fn proxy_failed(&self, operation: &str, error: &anyhow::Error) {
+        self.proxy_failures
+            .with_label_values(&[operation, proxy_error_code(error).as_str()])
+            .inc();
+    }
+
+    /// A proxied call that failed and is retried.
+    pub(crate) fn proxy_retried(&self, operation: &str, error: &anyhow::Error) {
+        self.proxy_retries
+            .with_label_values(&[operation, proxy_error_code(error).as_str()])
+            .inc();
+    }
+
+    /// Registry the service can add metrics of its own to, served with the kafence ones.
+    pub(crate) fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Metrics in the Prometheus text format.
+    pub(crate) fn encode(&self) -> Result<String> {
+        let mut buffer = vec![];
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+/// Errors answered by the other instance keep their code, any other one is a `transport` error.
+fn proxy_error_code(error: &anyhow::Error) -> String {
+    error
+        .downcast_ref::<KafenceError>()
+        .and_then(|error| serde_json::to_value(error.code).ok())
+        .and_then(|code| code.as_str().map(str::to_string))
+        .unwrap_or_else(|| "transport".to_string())
+}
@@ -0,0 +1,430 @@
+use crate::metrics::KafenceMetrics;
+use crate::{
+    KafenceProducer, KafenceQuery, StrongConsistencyPath, WriteCondition, check_record_key,
+    decode_fields, decode_routed_partitions, encode_entries, partition_for_key,
+};
+use anyhow::Result;
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use hyper::header::HeaderValue;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::fmt;
+use std::net::TcpListener;
+use std::sync::Arc;
+
+pub(crate) const RECORDS_PATH: &str = "/records";
+/// Path proxied writes were sent to before `/records`, still accepted for writes of older instances.
+pub(crate) const LEGACY_WRITE_PATH: &str = "/";
+pub(crate) const MULTI_GET_PATH: &str = "/records/multi-get";
+pub(crate) const METRICS_PATH: &str = "/metrics";
+pub(crate) const RECORD_KEY_HEADER: &str = "record-key";
+pub(crate) const RECORD_PREFIX_HEADER: &str = "record-prefix";
+pub(crate) const RECORD_KEY_ENCODING_HEADER: &str = "record-key-encoding";
+pub(crate) const BASE64_KEY_ENCODING: &str = "base64";
+pub(crate) const PROXIED_HEADER: &str = "x-kafence-proxied";
+pub(crate) const ROUTE_EPOCH_HEADER: &str = "x-kafence-route-epoch";
+pub(crate) const ROUTE_PARTITIONS_HEADER: &str = "x-kafence-route-partitions";
+pub(crate) const ROUTE_HEADER: &str = "x-kafence-route";
+pub(crate) const PROXY_TARGET_HEADER: &str = "x-kafence-proxy-target";
+pub(crate) const ERROR_HEADER: &str = "x-kafence-error";
+pub(crate) const VERSION_HEADER: &str = "x-kafence-version";
+pub(crate) const IF_VERSION_HEADER: &str = "x-kafence-if-version";
+pub(crate) const IF_VALUE_HEADER: &str = "x-kafence-if-value";
+pub(crate) const ABSENT_VERSION: &str = "absent";
+
+// Errors
+// ------
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum KafenceErrorCode {
+    BadRequest,
+    NotFound,
+    MethodNotAllowed,
+    NotOwner,
+    ConditionFailed,
+    RouteNotReady,
+    StoreNotReady,
+    ProxyFailed,
+    Unavailable,
+}
+
+/// Error returned by the router as a JSON body, with the code also in the `x-kafence-error` header.
+/// Proxied calls read it back, so the original caller receives the error of the owner.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct KafenceError {
+    pub(crate) code: KafenceErrorCode,
+    pub(crate) message: String,
+}
+
+impl KafenceErrorCode {
+    fn status(self) -> StatusCode {
+        match self {
+            KafenceErrorCode::BadRequest => StatusCode::BAD_REQUEST,
+            KafenceErrorCode::NotFound => StatusCode::NOT_FOUND,
+            KafenceErrorCode::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            KafenceErrorCode::NotOwner => StatusCode::CONFLICT,
+            KafenceErrorCode::ConditionFailed => StatusCode::PRECONDITION_FAILED,
+            KafenceErrorCode::RouteNotReady
+            | KafenceErrorCode::StoreNotReady
+            | KafenceErrorCode::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            KafenceErrorCode::ProxyFailed => StatusCode::BAD_GATEWAY,
+        }
+    }
+}
+
+impl KafenceError {
+    pub(crate) fn new(code: KafenceErrorCode, message: impl Into<String>) -> KafenceError {
+        KafenceError {
+            code,
+            message: message.into(),
+        }
+    }
+
+    /// Errors raised by kafence keep their code, any other one is [KafenceErrorCode::Unavailable].
+    pub(crate) fn from_anyhow(error: anyhow::Error) -> KafenceError {
+        match error.downcast::<KafenceError>() {
+            Ok(error) => error,
+            Err(error) => KafenceError::new(KafenceErrorCode::Unavailable, error.to_string()),
+        }
+    }
+
+    pub(crate) async fn from_response(response: Response<Body>) -> KafenceError {
+        let status = response.status();
+        match hyper::body::to_bytes(response.into_body()).await {
+            Ok(body) => serde_json::from_slice(&body).unwrap_or_else(|_| {
+                KafenceError::new(
+                    KafenceErrorCode::ProxyFailed,
+                    format!("proxy failed with status {status}"),
+                )
+            }),
+            Err(e) => KafenceError::new(
+                KafenceErrorCode::ProxyFailed,
+                format!("proxy failed with status {status}: {e}"),
+            ),
+        }
+    }
+
+    pub(crate) fn into_response(self) -> Response<Body> {
+        let body = serde_json::to_vec(&self).unwrap_or_default();
+        let mut response = Response::new(Body::from(body));
+        *response.status_mut() = self.code.status();
+        response.headers_mut().insert(
+            hyper::header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+        let code = serde_json::to_value(self.code).ok().and_then(|code| {
+            code.as_str()
+                .and_then(|code| HeaderValue::from_str(code).ok())
+        });
+        if let Some(code) = code {
+            response.headers_mut().insert(ERROR_HEADER, code);
+        }
+        response
+    }
+}
+
+impl fmt::Display for KafenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for KafenceError {}
+
+// Router
+// ------
+
+/// Endpoint serving the writes and reads of `/records`, proxied by other instances or sent by
+/// clients, and the metrics of the instance on `/metrics`. It can be mounted in an existing hyper
+/// service with [KafenceRouter::handles] and [KafenceRouter::handle], or run on its own with
+/// [KafenceRouter::serve].
+///
+/// Keys and prefixes are read as they are from the headers, or base64 decoded with
+/// `record-key-encoding: base64`, and values are the binary body, so records keep their exact bytes.
+///
+/// A request with the `x-kafence-proxied` header is never proxied again. If this instance does not
+/// own the partition of the key anymore, it answers `not_owner` instead, so two instances with
+/// different route tables cannot send a request back and forth.
+#[derive(Clone)]
+pub(crate) struct KafenceRouter {
+    topic: String,
+    producer: Arc<KafenceProducer<Vec<u8>, Vec<u8>>>,
+    query: Arc<KafenceQuery<Vec<u8>, Vec<u8>>>,
+    metrics: Arc<KafenceMetrics>,
+}
+
+impl KafenceRouter {
+    pub(crate) fn new(
+        topic: &str,
+        producer: KafenceProducer<Vec<u8>, Vec<u8>>,
+        query: KafenceQuery<Vec<u8>, Vec<u8>>,
+        metrics: Arc<KafenceMetrics>,
+    ) -> Self {
+        KafenceRouter {
+            topic: topic.to_string(),
+            producer: Arc::new(producer),
+            query: Arc::new(query),
+            metrics,
+        }
+    }
+
+    pub(crate) fn handles(request: &Request<Body>) -> bool {
+        match request.uri().path() {
+            RECORDS_PATH | MULTI_GET_PATH | METRICS_PATH => true,
+            LEGACY_WRITE_PATH => request.method() == Method::POST,
+            _ => false,
+        }
+    }
+
+    pub(crate) async fn handle(&self, request: Request<Body>) -> Response<Body> {
+        let result = match (request.method(), request.uri().path()) {
+            (&Method::POST, RECORDS_PATH | LEGACY_WRITE_PATH) => self.write(request).await,
+            (&Method::GET, RECORDS_PATH) => self.read(request).await,
+            (&Method::POST, MULTI_GET_PATH) => self.multi_get(request).await,
+            (&Method::GET, METRICS_PATH) => self.metrics(),
+            (_, path @ (RECORDS_PATH | MULTI_GET_PATH | METRICS_PATH)) => Err(KafenceError::new(
+                KafenceErrorCode::MethodNotAllowed,
+                format!("{} is not supported on {path}", request.method()),
+            )),
+            (_, path) => Err(KafenceError::new(
+                KafenceErrorCode::NotFound,
+                format!("no kafence route for {path}"),
+            )),
+        };
+        result.unwrap_or_else(KafenceError::into_response)
+    }
+
+    pub(crate) async fn serve(self, listener: TcpListener) -> Result<()> {
+        let server = Server::from_tcp(listener)?.serve(make_service_fn(move |_conn| {
+            let router = self.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let router = router.clone();
+                    async move { Ok::<_, Infallible>(router.handle(request).await) }
+                }))
+            }
+        }));
+        server.await?;
+        Ok(())
+    }
+
+    async fn write(&self, request: Request<Body>) -> Result<Response<Body>, KafenceError> {
+        let proxied = request.headers().contains_key(PROXIED_HEADER);
+        let request_epoch = header(&request, ROUTE_EPOCH_HEADER);
+        let key = required_record_key(&request, RECORD_KEY_HEADER)?;
+        check_record_key(&key)?;
+        let condition = write_condition(&request)?;
+        let value = hyper::body::to_bytes(request.into_body())
+            .await
+            .map_err(|e| {
+                KafenceError::new(KafenceErrorCode::BadRequest, format!("invalid body: {e}"))
+            })?
+            .to_vec();
+
+        let path = if proxied {
+            let partition = self.owned_partition(&request_epoch, &key)?;
+            self.producer
+                .local_persistance_encoded(&self.topic, partition, &key, &value, &condition)
+                .await
+                .map(|_| StrongConsistencyPath::Local)
+        } else {
+            self.producer
+                .strong_consistency_encoded(&self.topic, &key, &value, &condition)
+                .await
+        };
+
+        let key = String::from_utf8_lossy(&key);
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::ACCEPTED;
+        match path.map_err(KafenceError::from_anyhow)? {
+            StrongConsistencyPath::Local => {
+                response
+                    .headers_mut()
+                    .insert(ROUTE_HEADER, HeaderValue::from_static("local"));
+                *response.body_mut() = Body::from(format!("published {key}"));
+            }
+            StrongConsistencyPath::Proxied(target_host) => {
+                response
+                    .headers_mut()
+                    .insert(ROUTE_HEADER, HeaderValue::from_static("proxy"));
+                if let Ok(target_host) = HeaderValue::from_str(&target_host) {
+                    response
+                        .headers_mut()
+                        .insert(PROXY_TARGET_HEADER, target_host);
+                }
+                *response.body_mut() = Body::from(format!("proxied {key}"));
+            }
+        }
+        Ok(response)
+    }
+
+    async fn read(&self, request: Request<Body>) -> Result<Response<Body>, KafenceError> {
+        let proxied = request.headers().contains_key(PROXIED_HEADER);
+        if let Some(prefix) = record_key(&request, RECORD_PREFIX_HEADER)? {
+            let entries = if proxied {
+                let partitions = header(&request, ROUTE_PARTITIONS_HEADER).ok_or_else(|| {
+                    KafenceError::new(
+                        KafenceErrorCode::BadRequest,
+                        format!("{ROUTE_PARTITIONS_HEADER} header is required"),
+                    )
+                })?;
+                let partitions = decode_routed_partitions(&partitions).map_err(|e| {
+                    KafenceError::new(
+                        KafenceErrorCode::BadRequest,
+                        format!("invalid {ROUTE_PARTITIONS_HEADER}: {e}"),
+                    )
+                })?;
+                self.query.owned_prefix_scan_encoded(&prefix, &partitions)
+            } else {
+                self.query.prefix_scan_encoded(&prefix).await
+            };
+            let entries = entries.map_err(KafenceError::from_anyhow)?;
+            return Ok(Response::new(Body::from(encode_entries(&entries))));
+        }
+
+        let key = required_record_key(&request, RECORD_KEY_HEADER)?;
+        let value = if proxied {
+            self.owned_partition(&header(&request, ROUTE_EPOCH_HEADER), &key)?;
+            self.query.local_get_encoded(&key)
+        } else {
+            self.query.get_encoded(&key).await
+        };
+        match value.map_err(KafenceError::from_anyhow)? {
+            Some((value, version)) => {
+                let mut response = Response::new(Body::from(value));
+                response
+                    .headers_mut()
+                    .insert(VERSION_HEADER, HeaderValue::from(version));
+                Ok(response)
+            }
+            None => Err(KafenceError::new(
+                KafenceErrorCode::NotFound,
+                format!("record {} not found", String::from_utf8_lossy(&key)),
+            )),
+        }
+    }
+
+    /// Keys in the body, as written by [crate::encode_fields], and the entries of the keys found
+    /// in the response.
+    async fn multi_get(&self, request: Request<Body>) -> Result<Response<Body>, KafenceError> {
+        let proxied = request.headers().contains_key(PROXIED_HEADER);
+        let body = hyper::body::to_bytes(request.into_body())
+            .await
+            .map_err(|e| {
+                KafenceError::new(KafenceErrorCode::BadRequest, format!("invalid body: {e}"))
+            })?;
+        let keys = decode_fields(&body).map_err(|e| {
+            KafenceError::new(KafenceErrorCode::BadRequest, format!("invalid keys: {e}"))
+        })?;
+        let values = if proxied {
+            self.query.owned_multi_get_encoded(&keys)
+        } else {
+            self.query.multi_get_encoded(&keys).await
+        };
+        let entries = keys
+            .into_iter()
+            .zip(values.map_err(KafenceError::from_anyhow)?)
+            .filter_map(|(key, value)| value.map(|value| (key, value)))
+            .collect::<Vec<_>>();
+        Ok(Response::new(Body::from(encode_entries(&entries))))
+    }
+
+    fn metrics(&self) -> Result<Response<Body>, KafenceError> {
+        let metrics = self.metrics.encode().map_err(KafenceError::from_anyhow)?;
+        let mut response = Response::new(Body::from(metrics));
+        response.headers_mut().insert(
+            hyper::header::CONTENT_TYPE,
+            HeaderValue::from_static("text/plain; version=0.0.4"),
+        );
+        Ok(response)
+    }
+
+    /// Partition of the key, if this instance owns it with the epoch of the route the caller used,
+    /// or a newer one.
+    fn owned_partition(
+        &self,
+        request_epoch: &Option<String>,
+        key: &[u8],
+    ) -> Result<i32, KafenceError> {
+        let request_epoch = request_epoch
+            .as_deref()
+            .map(str::parse::<u64>)
+            .transpose()
+            .map_err(|e| {
+                KafenceError::new(
+                    KafenceErrorCode::BadRequest,
+                    format!("invalid {ROUTE_EPOCH_HEADER}: {e}"),
+                )
+            })?;
+        let partition = partition_for_key(key, self.query.partitions);
+        self.query.owned_epoch(partition, request_epoch)?;
+        Ok(partition)
+    }
+}
+
+fn header(request: &Request<Body>, name: &str) -> Option<String> {
+    request
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned)
+}
+
+fn record_key(request: &Request<Body>, name: &str) -> Result<Option<Vec<u8>>, KafenceError> {
+    let Some(key) = header(request, name) else {
+        return Ok(None);
+    };
+    match header(request, RECORD_KEY_ENCODING_HEADER).as_deref() {
+        None => Ok(Some(key.into_bytes())),
+        Some(BASE64_KEY_ENCODING) => BASE64_STANDARD.decode(key).map(Some).map_err(|e| {
+            KafenceError::new(
+                KafenceErrorCode::BadRequest,
+                format!("invalid base64 {name}: {e}"),
+            )
+        }),
+        Some(encoding) => Err(KafenceError::new(
+            KafenceErrorCode::BadRequest,
+            format!("unsupported {RECORD_KEY_ENCODING_HEADER} {encoding}"),
+        )),
+    }
+}
+
+/// Condition of a write, from `x-kafence-if-version`, a version or `absent`, or from
+/// `x-kafence-if-value`, the base64 expected value.
+fn write_condition(request: &Request<Body>) -> Result<WriteCondition, KafenceError> {
+    let bad_request = |name: &str, e: &dyn fmt::Display| {
+        KafenceError::new(KafenceErrorCode::BadRequest, format!("invalid {name}: {e}"))
+    };
+    match (
+        header(request, IF_VERSION_HEADER),
+        header(request, IF_VALUE_HEADER),
+    ) {
+        (None, None) => Ok(WriteCondition::Always),
+        (Some(version), None) if version == ABSENT_VERSION => Ok(WriteCondition::Version(None)),
+        (Some(version), None) => version
+            .parse()
+            .map(|version| WriteCondition::Version(Some(version)))
+            .map_err(|e| bad_request(IF_VERSION_HEADER, &e)),
+        (None, Some(value)) => BASE64_STANDARD
+            .decode(value)
+            .map(WriteCondition::Value)
+            .map_err(|e| bad_request(IF_VALUE_HEADER, &e)),
+        (Some(_), Some(_)) => Err(KafenceError::new(
+            KafenceErrorCode::BadRequest,
+            format!("{IF_VERSION_HEADER} and {IF_VALUE_HEADER} cannot be combined"),
+        )),
+    }
+}
+
+fn required_record_key(request: &Request<Body>, name: &str) -> Result<Vec<u8>, KafenceError> {
+    record_key(request, name)?.ok_or_else(|| {
+        KafenceError::new(
+            KafenceErrorCode::BadRequest,
+            format!("{name} header is required"),
+        )
+    })
+}
@@ -0,0 +1,218 @@
+use anyhow::Result;
+use rocksdb::{DB, Direction, IteratorMode, Options};
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+pub(crate) type KafenceEntry = (Vec<u8>, Vec<u8>);
+
+/// Prefix of the keys kafence keeps next to the records. They are never served to readers.
+const RESERVED_KEY_PREFIX: &[u8] = b"\0kafence:";
+
+/// Local state materialized from the topic. Every instance keeps the records of the partitions it
+/// consumes, so the store is never shared between instances.
+pub(crate) trait StateStore: Send + Sync {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
+
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<()>;
+
+    fn delete(&self, key: &[u8]) -> Result<()>;
+
+    /// Entries whose key starts with `prefix`, sorted by key.
+    fn scan(&self, prefix: &[u8]) -> Result<Vec<KafenceEntry>>;
+
+    fn flush(&self) -> Result<()>;
+
+    /// Flush and release the store. Every later call fails.
+    fn close(&self) -> Result<()>;
+}
+
+// Stored Offsets
+// --------------
+
+pub(crate) fn is_reserved_key(key: &[u8]) -> bool {
+    key.starts_with(RESERVED_KEY_PREFIX)
+}
+
+fn offset_key(topic: &str, partition: i32) -> Vec<u8> {
+    [
+        RESERVED_KEY_PREFIX,
+        format!("offset:{topic}:{partition}").as_bytes(),
+    ]
+    .concat()
+}
+
+/// Offset of the next record of the partition to materialize, stored with the records so a
+/// restarted instance, or a new owner of the partition, knows where to restore from.
+pub(crate) fn stored_offset(
+    state_store: &dyn StateStore,
+    topic: &str,
+    partition: i32,
+) -> Result<Option<i64>> {
+    match state_store.get(&offset_key(topic, partition))? {
+        Some(offset) => {
+            let offset: [u8; 8] = offset
+                .as_slice()
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("invalid stored offset for {topic}:{partition}"))?;
+            Ok(Some(i64::from_be_bytes(offset)))
+        }
+        None => Ok(None),
+    }
+}
+
+pub(crate) fn store_offset(
+    state_store: &dyn StateStore,
+    topic: &str,
+    partition: i32,
+    offset: i64,
+) -> Result<()> {
+    state_store.put(&offset_key(topic, partition), &offset.to_be_bytes())
+}
+
+// Stored Versions
+// ---------------
+
+fn version_key(key: &[u8]) -> Vec<u8> {
+    [RESERVED_KEY_PREFIX, b"version:", key].concat()
+}
+
+/// Version of the record of the key, the offset that wrote it plus one, so version `0` is left to
+/// records materialized before versions were stored.
+pub(crate) fn stored_version(state_store: &dyn StateStore, key: &[u8]) -> Result<Option<u64>> {
+    match state_store.get(&version_key(key))? {
+        Some(version) => {
+            let version: [u8; 8] = version
+                .as_slice()
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("invalid stored version"))?;
+            Ok(Some(u64::from_be_bytes(version)))
+        }
+        None => Ok(None),
+    }
+}
+
+pub(crate) fn store_version(state_store: &dyn StateStore, key: &[u8], version: u64) -> Result<()> {
+    state_store.put(&version_key(key), &version.to_be_bytes())
+}
+
+pub(crate) fn delete_version(state_store: &dyn StateStore, key: &[u8]) -> Result<()> {
+    state_store.delete(&version_key(key))
+}
+
+// RocksDB Store
+// -------------
+
+pub(crate) struct RocksDbStore {
+    db: RwLock<Option<DB>>,
+}
+
+impl RocksDbStore {
+    pub(crate) fn open(path: &str) -> Result<RocksDbStore> {
+        let mut options = Options::default();
+        options.create_if_missing(true);
+        Ok(RocksDbStore {
+            db: RwLock::new(Some(DB::open(&options, path)?)),
+        })
+    }
+
+    fn with_db<T>(&self, f: impl FnOnce(&DB) -> Result<T>) -> Result<T> {
+        match self.db.read().unwrap().as_ref() {
+            Some(db) => f(db),
+            None => anyhow::bail!("state store is closed"),
+        }
+    }
+}
+
+impl StateStore for RocksDbStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.with_db(|db| Ok(db.get(key)?))
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.with_db(|db| Ok(db.put(key, value)?))
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<()> {
+        self.with_db(|db| Ok(db.delete(key)?))
+    }
+
+    fn scan(&self, prefix: &[u8]) -> Result<Vec<KafenceEntry>> {
+        self.with_db(|db| {
+            let mut entries = vec![];
+            for entry in db.iterator(IteratorMode::From(prefix, Direction::Forward)) {
+                let (key, value) = entry?;
+                if !key.starts_with(prefix) {
+                    break;
+                }
+                entries.push((key.to_vec(), value.to_vec()));
+            }
+            Ok(entries)
+        })
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.with_db(|db| Ok(db.flush()?))
+    }
+
+    /// Dropping the database closes it, and releases its lock so the path can be opened again.
+    fn close(&self) -> Result<()> {
+        if let Some(db) = self.db.write().unwrap().take() {
+            db.flush()?;
+        }
+        Ok(())
+    }
+}
+
+// In Memory Store
+// ---------------
+
+/// Store kept in the process memory, for unit tests and ephemeral caches.
+/// The state is lost on restart, so the instance has to replay the topic to rebuild it.
+#[derive(Default)]
+pub(crate) struct InMemoryStore {
+    entries: RwLock<BTreeMap<Vec<u8>, Vec<u8>>>,
+}
+
+impl InMemoryStore {
+    pub(crate) fn new() -> InMemoryStore {
+        InMemoryStore::default()
+    }
+}
+
+impl StateStore for InMemoryStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.entries.read().unwrap().get(key).cloned())
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.entries
+            .write()
+            .unwrap()
+            .insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, key: &[u8]) -> Result<()> {
+        self.entries.write().unwrap().remove(key);
+        Ok(())
+    }
+
+    fn scan(&self, prefix: &[u8]) -> Result<Vec<KafenceEntry>> {
+        Ok(self
+            .entries
+            .read()
+            .unwrap()
+            .range(prefix.to_vec()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect())
+    }
+
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn close(&self) -> Result<()> {
+        Ok(())
+    }
+}
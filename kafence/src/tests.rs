@@ -1,14 +1,23 @@
-use crate::{Kafence, KafenceProducerContract, StrongConsistencyPath};
-use hyper::header::HeaderValue;
-use hyper::service::{make_service_fn, service_fn};
-use hyper::{Body, Client, Method, Request, Response, Server, StatusCode};
+use crate::codec::{BytesCodec, CborCodec, Codec, JsonCodec};
+use crate::router::{KafenceError, KafenceErrorCode, KafenceRouter};
+use crate::store::{InMemoryStore, RocksDbStore, StateStore, stored_offset};
+use crate::transport::TransportRecord;
+use crate::transport::{InProcessTransport, RebalanceListener, Transport};
+use crate::{
+    Kafence, KafenceProducerContract, KafenceQueryContract, PartitionAssignment, RouteEntry,
+    StrongConsistencyPath,
+};
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use hyper::{Body, Client, Method, Request, Response, StatusCode};
 use rdkafka::ClientConfig;
 use rdkafka::admin::{AdminClient, AdminOptions, NewTopic, TopicReplication};
 use rdkafka::client::DefaultClientContext;
 use rdkafka::error::RDKafkaErrorCode;
-use std::convert::Infallible;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::net::TcpListener;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 
@@ -55,18 +64,12 @@ async fn producer_test() {
     let service_2_addr = service_2_listener.local_addr().unwrap();
     let service_2_url = format!("http://{}", service_2_addr);
 
-    let uuid = uuid::Uuid::new_v4();
-    let rocksdb_path_2 = std::env::temp_dir()
-        .join(format!("kafence-{run_id}-{uuid}"))
-        .to_string_lossy()
-        .into_owned();
-
     let kaference_2 = Kafence::new()
         .with_brokers(broker.to_string())
         .with_topic(&topic)
         .with_consumer_group(&consumer_group)
         .with_partitions(2)
-        .with_rocksdb_path(&rocksdb_path_2)
+        .with_state_store(InMemoryStore::new())
         .with_service_url(&service_2_url)
         .build();
     let service_2 = tokio::spawn(run_server(service_2_listener, Arc::clone(&kaference_2)));
@@ -120,101 +123,1076 @@ async fn producer_test() {
             .unwrap()
     );
 
+    // Read your own writes from any instance
+    // --------------------------------------
+    assert_eq!(
+        Some("hello local world".to_string()),
+        get_record(&client, service_1_addr, &local_key).await
+    );
+    assert_eq!(
+        Some("hello proxy world".to_string()),
+        get_record(&client, service_1_addr, &proxy_key).await
+    );
+    assert_eq!(
+        Some("hello local world".to_string()),
+        get_record(&client, service_2_addr, &local_key).await
+    );
+    assert_eq!(
+        None,
+        get_record(&client, service_1_addr, "missing_record_key").await
+    );
+
+    let query = kaference_1.query();
+    let values = query
+        .multi_get(vec![local_key.clone(), proxy_key.clone()])
+        .await
+        .unwrap();
+    assert_eq!(
+        vec![
+            Some(b"hello local world".to_vec()),
+            Some(b"hello proxy world".to_vec())
+        ],
+        values
+    );
+
+    let entries = query
+        .prefix_scan("proxy_record_key".to_string())
+        .await
+        .unwrap();
+    assert_eq!(
+        vec![(proxy_key.clone(), b"hello proxy world".to_vec())],
+        entries
+    );
+
     tokio::time::sleep(Duration::from_secs(5)).await;
 
     service_1.abort();
     service_2.abort();
 }
 
-struct TestServiceState {
-    topic: String,
-    producer: crate::KafenceProducer,
-}
+#[tokio::test]
+async fn in_process_producer_test() {
+    let topic = "orders";
+    let transport = InProcessTransport::new();
+    transport.create_topic(topic, 2, false).await.unwrap();
 
-pub async fn run_server(listener: TcpListener, kafence: Arc<Kafence>) {
-    println!("Preparing Service...");
-    kafence.stream().await.unwrap();
-    let state = Arc::new(TestServiceState {
-        topic: kafence.topic.clone(),
-        producer: kafence.producer().unwrap(),
-    });
+    let service_1_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let service_1_addr = service_1_listener.local_addr().unwrap();
+    let service_1_url = format!("http://{}", service_1_addr);
+    let kaference_1 = Kafence::new()
+        .with_topic(topic)
+        .with_consumer_group("orders-service")
+        .with_partitions(2)
+        .with_state_store(InMemoryStore::new())
+        .with_transport(transport.clone())
+        .with_service_url(&service_1_url)
+        .build();
+    let service_1 = tokio::spawn(run_server(service_1_listener, Arc::clone(&kaference_1)));
 
-    let server = Server::from_tcp(listener)
-        .unwrap()
-        .serve(make_service_fn(move |_conn| {
-            let state = Arc::clone(&state);
-            async move {
-                let state = Arc::clone(&state);
-                Ok::<_, Infallible>(service_fn(move |request| {
-                    create_service(request, Arc::clone(&state))
-                }))
-            }
-        }));
-    if let Err(e) = server.await {
-        println!("server error: {}", e);
+    let service_2_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let service_2_addr = service_2_listener.local_addr().unwrap();
+    let service_2_url = format!("http://{}", service_2_addr);
+    let kaference_2 = Kafence::new()
+        .with_topic(topic)
+        .with_consumer_group("orders-service")
+        .with_partitions(2)
+        .with_state_store(InMemoryStore::new())
+        .with_transport(transport.clone())
+        .with_service_url(&service_2_url)
+        .build();
+    let service_2 = tokio::spawn(run_server(service_2_listener, Arc::clone(&kaference_2)));
+
+    wait_for_route_owners(&kaference_1, 2).await;
+    wait_for_route_owners(&kaference_2, 2).await;
+
+    let local_partition = partition_owned_by(&kaference_1, &service_1_url);
+    let proxy_partition = partition_not_owned_by(&kaference_1, &service_1_url);
+    assert_eq!(service_2_url, route_owner(&kaference_1, proxy_partition));
+
+    let local_key = key_for_partition("local_record_key", local_partition, 2);
+    let proxy_key = key_for_partition("proxy_record_key", proxy_partition, 2);
+
+    let client = Client::new();
+    let local_response =
+        post_record(&client, service_1_addr, &local_key, "hello local world").await;
+    assert_eq!(StatusCode::ACCEPTED, local_response.status());
+    assert_eq!("local", local_response.headers()["x-kafence-route"]);
+
+    let proxy_response =
+        post_record(&client, service_1_addr, &proxy_key, "hello proxy world").await;
+    assert_eq!(StatusCode::ACCEPTED, proxy_response.status());
+    assert_eq!("proxy", proxy_response.headers()["x-kafence-route"]);
+
+    assert_eq!(
+        Some("hello proxy world".to_string()),
+        get_record(&client, service_1_addr, &proxy_key).await
+    );
+    assert_eq!(
+        Some("hello local world".to_string()),
+        get_record(&client, service_2_addr, &local_key).await
+    );
+
+    // Keys of other owners are read with one request per owner
+    let missing_key = key_for_partition("missing_record_key", proxy_partition, 2);
+    assert_eq!(
+        vec![
+            Some(b"hello proxy world".to_vec()),
+            Some(b"hello local world".to_vec()),
+            None,
+        ],
+        kaference_1
+            .query()
+            .multi_get(vec![proxy_key.clone(), local_key.clone(), missing_key])
+            .await
+            .unwrap()
+    );
+
+    // A proxied scan of a partition the instance does not own fails instead of skipping its records
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri(format!("http://{service_2_addr}/records"))
+        .header("record-prefix", "local_record_key")
+        .header("x-kafence-proxied", "true")
+        .header("x-kafence-route-partitions", format!("{local_partition}:0"))
+        .body(Body::empty())
+        .unwrap();
+    let scan_response = client.request(request).await.unwrap();
+    assert_eq!(StatusCode::CONFLICT, scan_response.status());
+    assert_eq!("not_owner", scan_response.headers()["x-kafence-error"]);
+
+    // A proxied write that reaches an instance that does not own the key is not proxied again
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(format!("http://{service_2_addr}/records"))
+        .header("record-key", &local_key)
+        .header("x-kafence-proxied", "true")
+        .body(Body::from("looping world"))
+        .unwrap();
+    let loop_response = client.request(request).await.unwrap();
+    assert_eq!(StatusCode::CONFLICT, loop_response.status());
+    assert_eq!("not_owner", loop_response.headers()["x-kafence-error"]);
+    let error = KafenceError::from_response(loop_response).await;
+    assert_eq!(KafenceErrorCode::NotOwner, error.code);
+
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(format!("http://{service_1_addr}/records"))
+        .body(Body::from("world without key"))
+        .unwrap();
+    let bad_response = client.request(request).await.unwrap();
+    assert_eq!(StatusCode::BAD_REQUEST, bad_response.status());
+    assert_eq!(
+        KafenceErrorCode::BadRequest,
+        KafenceError::from_response(bad_response).await.code
+    );
+
+    // A stale route is fenced by the instance it points to, and the write is retried once the
+    // route table is fixed
+    stale_route_for(&kaference_1, local_partition, &service_2_url);
+    let retry_key = key_for_partition("retry_record_key", local_partition, 2);
+    let retry_response =
+        post_record(&client, service_1_addr, &retry_key, "hello retried world").await;
+    assert_eq!(StatusCode::ACCEPTED, retry_response.status());
+    assert_eq!("local", retry_response.headers()["x-kafence-route"]);
+
+    // Reads follow the partition the same way
+    stale_route_for(&kaference_1, local_partition, &service_2_url);
+    assert_eq!(
+        Some("hello retried world".to_string()),
+        get_record(&client, service_1_addr, &retry_key).await
+    );
+    stale_route_for(&kaference_1, local_partition, &service_2_url);
+    assert_eq!(
+        vec![
+            Some(b"hello retried world".to_vec()),
+            Some(b"hello proxy world".to_vec()),
+        ],
+        kaference_1
+            .query()
+            .multi_get(vec![retry_key.clone(), proxy_key.clone()])
+            .await
+            .unwrap()
+    );
+
+    // Writes, acks, routes and proxy retries are exposed in the Prometheus text format
+    let metrics = get_metrics(&client, service_1_addr).await;
+    let client_id = &kaference_1.client_id;
+    for line in [
+        format!("kafence_writes_total{{path=\"local\",client_id=\"{client_id}\"}} 2"),
+        format!("kafence_writes_total{{path=\"proxied\",client_id=\"{client_id}\"}} 1"),
+        format!(
+            "kafence_write_duration_seconds_count{{path=\"local\",client_id=\"{client_id}\"}} 2"
+        ),
+        format!("kafence_ack_latency_seconds_count{{client_id=\"{client_id}\"}} 2"),
+        format!("kafence_route_table_size{{client_id=\"{client_id}\"}} 2"),
+    ] {
+        assert!(
+            metrics.lines().any(|metric| metric == line),
+            "{line} not in {metrics}"
+        );
     }
+    assert!(metrics.contains(&format!(
+        "kafence_proxy_retries_total{{code=\"not_owner\",operation=\"write\",client_id=\"{client_id}\"}}"
+    )));
+    assert!(metrics.contains(&format!(
+        "kafence_proxy_retries_total{{code=\"not_owner\",operation=\"get\",client_id=\"{client_id}\"}}"
+    )));
+    assert!(metrics.contains(&format!(
+        "kafence_proxy_retries_total{{code=\"not_owner\",operation=\"multi_get\",client_id=\"{client_id}\"}}"
+    )));
+    assert!(!metrics.contains("kafence_proxy_failures_total{"));
+
+    // Writes proxied by older instances to `/` are still served
+    let legacy_key = key_for_partition("legacy_record_key", local_partition, 2);
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(format!("http://{service_1_addr}/"))
+        .header("record-key", &legacy_key)
+        .header("x-kafence-proxied", "true")
+        .body(Body::from("hello legacy world"))
+        .unwrap();
+    assert!(KafenceRouter::handles(&request));
+    let legacy_response = client.request(request).await.unwrap();
+    assert_eq!(StatusCode::ACCEPTED, legacy_response.status());
+    assert_eq!(
+        Some("hello legacy world".to_string()),
+        get_record(&client, service_2_addr, &legacy_key).await
+    );
+    let request = Request::builder()
+        .uri(format!("http://{service_1_addr}/"))
+        .body(Body::empty())
+        .unwrap();
+    assert!(!KafenceRouter::handles(&request));
+
+    service_1.abort();
+    service_2.abort();
 }
 
-async fn create_service(
-    req: Request<Body>,
-    state: Arc<TestServiceState>,
-) -> Result<Response<Body>, Infallible> {
-    let mut response = Response::new(Body::empty());
-    match (req.method(), req.uri().path()) {
-        (&Method::GET, "/") => {
-            *response.body_mut() = Body::from("Service is running");
+#[tokio::test]
+async fn in_process_restore_and_standby() {
+    let topic = "payments";
+    let transport = InProcessTransport::new();
+    transport.create_topic(topic, 2, false).await.unwrap();
+
+    let service_1_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let service_1_addr = service_1_listener.local_addr().unwrap();
+    let service_1_url = format!("http://{}", service_1_addr);
+    let kaference_1 = Kafence::new()
+        .with_topic(topic)
+        .with_consumer_group("payments-service")
+        .with_partitions(2)
+        .with_state_store(InMemoryStore::new())
+        .with_transport(transport.clone())
+        .with_service_url(&service_1_url)
+        .build();
+    let service_1 = tokio::spawn(run_server(service_1_listener, Arc::clone(&kaference_1)));
+    wait_for_routes(&kaference_1, 2).await;
+
+    let client = Client::new();
+    let keys = [
+        key_for_partition("restored_record_key", 0, 2),
+        key_for_partition("restored_record_key", 1, 2),
+    ];
+    for key in &keys {
+        let response = post_record(&client, service_1_addr, key, "hello restored world").await;
+        assert_eq!(StatusCode::ACCEPTED, response.status());
+    }
+    let state_store_1 = kaference_1.state_store.get().unwrap().as_ref();
+    assert_eq!(Some(1), stored_offset(state_store_1, topic, 0).unwrap());
+    assert_eq!(Some(1), stored_offset(state_store_1, topic, 1).unwrap());
+
+    // The second instance restores the partition it is assigned before routing it to itself
+    let service_2_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let service_2_addr = service_2_listener.local_addr().unwrap();
+    let service_2_url = format!("http://{}", service_2_addr);
+    let kaference_2 = Kafence::new()
+        .with_topic(topic)
+        .with_consumer_group("payments-service")
+        .with_partitions(2)
+        .with_state_store(InMemoryStore::new())
+        .with_transport(transport.clone())
+        .with_service_url(&service_2_url)
+        .with_standby(true)
+        .build();
+    let service_2 = tokio::spawn(run_server(service_2_listener, Arc::clone(&kaference_2)));
+    wait_for_route_owners(&kaference_2, 2).await;
+
+    let restored_partition = partition_owned_by(&kaference_2, &service_2_url);
+    let standby_partition = partition_not_owned_by(&kaference_2, &service_2_url);
+    let query_2 = kaference_2.query();
+    assert_eq!(
+        Some(b"hello restored world".to_vec()),
+        query_2
+            .local_get(&keys[restored_partition as usize])
+            .unwrap()
+    );
+    assert_eq!(
+        Some("hello restored world".to_string()),
+        get_record(&client, service_1_addr, &keys[restored_partition as usize]).await
+    );
+
+    // The standby keeps a shadow copy of the other partition, that is not served from it
+    let state_store_2 = kaference_2.state_store.get().unwrap().as_ref();
+    for _ in 0..100 {
+        if stored_offset(state_store_2, topic, standby_partition).unwrap() == Some(1) {
+            break;
         }
-        (&Method::POST, "/") => {
-            let key = req
-                .headers()
-                .get("record-key")
-                .and_then(|value| value.to_str().ok())
-                .map(str::to_owned)
-                .unwrap_or_else(|| format!("record_key_{}", Uuid::new_v4()));
-
-            match hyper::body::to_bytes(req.into_body()).await {
-                Ok(body) => {
-                    let value = String::from_utf8_lossy(&body).into_owned();
-                    match state
-                        .producer
-                        .strong_consistency(&state.topic, key.clone(), value)
-                        .await
-                    {
-                        Ok(StrongConsistencyPath::Local) => {
-                            response
-                                .headers_mut()
-                                .insert("x-kafence-route", HeaderValue::from_static("local"));
-                            *response.status_mut() = StatusCode::ACCEPTED;
-                            *response.body_mut() = Body::from(format!("published {key}"));
-                        }
-                        Ok(StrongConsistencyPath::Proxied(target_host)) => {
-                            response
-                                .headers_mut()
-                                .insert("x-kafence-route", HeaderValue::from_static("proxy"));
-                            response.headers_mut().insert(
-                                "x-kafence-proxy-target",
-                                HeaderValue::from_str(&target_host).unwrap(),
-                            );
-                            *response.status_mut() = StatusCode::ACCEPTED;
-                            *response.body_mut() = Body::from(format!("proxied {key}"));
-                        }
-                        Err(e) => {
-                            *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
-                            *response.body_mut() = Body::from(format!("publish failed: {e}"));
-                        }
-                    }
-                }
-                Err(e) => {
-                    *response.status_mut() = StatusCode::BAD_REQUEST;
-                    *response.body_mut() = Body::from(format!("invalid body: {e}"));
-                }
-            }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(
+        Some(b"hello restored world".to_vec()),
+        state_store_2
+            .get(keys[standby_partition as usize].as_bytes())
+            .unwrap()
+    );
+    assert_eq!(
+        vec![keys[restored_partition as usize].clone()],
+        query_2
+            .local_prefix_scan(&String::new())
+            .unwrap()
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect::<Vec<_>>()
+    );
+
+    service_1.abort();
+    service_2.abort();
+}
+
+#[tokio::test]
+async fn in_process_shutdown_handover() {
+    let topic = "invoices";
+    let transport = InProcessTransport::new();
+    transport.create_topic(topic, 2, false).await.unwrap();
+
+    let rocksdb_path = std::env::temp_dir()
+        .join(format!("kafence-shutdown-{}", Uuid::new_v4()))
+        .to_string_lossy()
+        .into_owned();
+    let service_1_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let service_1_url = format!("http://{}", service_1_listener.local_addr().unwrap());
+    let kaference_1 = Kafence::new()
+        .with_topic(topic)
+        .with_consumer_group("invoices-service")
+        .with_partitions(2)
+        .with_rocksdb_path(&rocksdb_path)
+        .with_transport(transport.clone())
+        .with_service_url(&service_1_url)
+        .build();
+    let handle_1 = kaference_1.stream().await.unwrap();
+    handle_1.ready().await.unwrap();
+    assert!(handle_1.is_ready());
+    let router_1 = kaference_1.router().unwrap();
+    let service_1 = tokio::spawn(async move { router_1.serve(service_1_listener).await });
+
+    let service_2_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let service_2_addr = service_2_listener.local_addr().unwrap();
+    let service_2_url = format!("http://{}", service_2_addr);
+    let kaference_2 = Kafence::new()
+        .with_topic(topic)
+        .with_consumer_group("invoices-service")
+        .with_partitions(2)
+        .with_state_store(InMemoryStore::new())
+        .with_transport(transport.clone())
+        .with_service_url(&service_2_url)
+        .build();
+    let service_2 = tokio::spawn(run_server(service_2_listener, Arc::clone(&kaference_2)));
+    wait_for_route_owners(&kaference_1, 2).await;
+    wait_for_route_owners(&kaference_2, 2).await;
+
+    let keys = [
+        key_for_partition("handover_record_key", 0, 2),
+        key_for_partition("handover_record_key", 1, 2),
+    ];
+    let producer_1 = kaference_1.producer().unwrap();
+    for key in &keys {
+        producer_1
+            .strong_consistency(topic, key.clone(), b"hello handed over world".to_vec())
+            .await
+            .unwrap();
+    }
+
+    handle_1.shutdown().await.unwrap();
+
+    // The remaining instance takes every partition over, and restores the records written before
+    for _ in 0..100 {
+        let routes = kaference_2.route_table.read().unwrap().clone();
+        if routes.len() == 2 && routes.values().all(|route| route.host == service_2_url) {
+            break;
         }
-        _ => {
-            *response.status_mut() = StatusCode::NOT_FOUND;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(service_2_url, route_owner(&kaference_2, 0));
+    assert_eq!(service_2_url, route_owner(&kaference_2, 1));
+    let client = Client::new();
+    for key in &keys {
+        assert_eq!(
+            Some("hello handed over world".to_string()),
+            get_record(&client, service_2_addr, key).await
+        );
+    }
+
+    // The stopped instance rejects writes, and its store is released
+    let error = producer_1
+        .strong_consistency(topic, keys[0].clone(), b"too late".to_vec())
+        .await
+        .unwrap_err();
+    assert_eq!(
+        Some(KafenceErrorCode::Unavailable),
+        error.downcast_ref::<KafenceError>().map(|error| error.code)
+    );
+    assert!(kaference_1.state_store.get().unwrap().get(b"key").is_err());
+    RocksDbStore::open(&rocksdb_path).unwrap();
+
+    service_1.abort();
+    service_2.abort();
+}
+
+#[tokio::test]
+async fn in_process_revoked_partition_acks() {
+    let topic = "receipts";
+    let transport = InProcessTransport::new();
+    transport.create_topic(topic, 2, false).await.unwrap();
+    let instance = |service_url: &str| {
+        Kafence::new()
+            .with_topic(topic)
+            .with_consumer_group("receipts-service")
+            .with_partitions(2)
+            .with_state_store(InMemoryStore::new())
+            .with_transport(transport.clone())
+            .with_service_url(service_url)
+            .build()
+    };
+
+    let kaference_1 = instance("http://service-1");
+    let handle_1 = kaference_1.stream().await.unwrap();
+    handle_1.ready().await.unwrap();
+
+    // Writers waiting for records of both partitions, when the second instance takes partition 1
+    let mut kept = crate::lock_materialization_key(
+        &kaference_1.materializer_ack,
+        key_for_partition("receipt", 0, 2).into_bytes(),
+    )
+    .await;
+    let mut revoked = crate::lock_materialization_key(
+        &kaference_1.materializer_ack,
+        key_for_partition("receipt", 1, 2).into_bytes(),
+    )
+    .await;
+    let kaference_2 = instance("http://service-2");
+    let _handle_2 = kaference_2.stream().await.unwrap();
+
+    assert!(
+        tokio::time::timeout(Duration::from_secs(5), &mut revoked)
+            .await
+            .unwrap()
+            .is_err()
+    );
+    assert_eq!(
+        Err(tokio::sync::oneshot::error::TryRecvError::Empty),
+        kept.try_recv()
+    );
+    assert_eq!(1, kaference_1.materializer_ack.read().unwrap().len());
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Order {
+    id: String,
+    amount: u64,
+    tags: Vec<String>,
+}
+
+#[tokio::test]
+async fn in_process_typed_records() {
+    let topic = "invoices";
+    let transport = InProcessTransport::new();
+    transport.create_topic(topic, 2, false).await.unwrap();
+
+    let service_1_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let service_1_url = format!("http://{}", service_1_listener.local_addr().unwrap());
+    let kaference_1 = Kafence::new()
+        .with_topic(topic)
+        .with_consumer_group("invoices-service")
+        .with_partitions(2)
+        .with_state_store(InMemoryStore::new())
+        .with_transport(transport.clone())
+        .with_service_url(&service_1_url)
+        .build();
+    let service_1 = tokio::spawn(run_server(service_1_listener, Arc::clone(&kaference_1)));
+
+    let service_2_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let service_2_url = format!("http://{}", service_2_listener.local_addr().unwrap());
+    let kaference_2 = Kafence::new()
+        .with_topic(topic)
+        .with_consumer_group("invoices-service")
+        .with_partitions(2)
+        .with_state_store(InMemoryStore::new())
+        .with_transport(transport.clone())
+        .with_service_url(&service_2_url)
+        .build();
+    let service_2 = tokio::spawn(run_server(service_2_listener, Arc::clone(&kaference_2)));
+
+    wait_for_route_owners(&kaference_1, 2).await;
+    wait_for_route_owners(&kaference_2, 2).await;
+    let proxy_partition = partition_not_owned_by(&kaference_1, &service_1_url);
+
+    // Binary keys and values are proxied and read back with their exact bytes
+    let binary_key = binary_key_for_partition(proxy_partition, 2);
+    let binary_value = vec![0xff, 0x00, 0xfe, b'o', b'k', 0x80];
+    let producer = kaference_1.typed_producer(BytesCodec, BytesCodec).unwrap();
+    let path = producer
+        .strong_consistency(topic, binary_key.clone(), binary_value.clone())
+        .await
+        .unwrap();
+    assert_eq!(StrongConsistencyPath::Proxied(service_2_url.clone()), path);
+    let query_1 = kaference_1.typed_query(BytesCodec, BytesCodec);
+    assert_eq!(
+        Some(binary_value.clone()),
+        query_1.get(binary_key.clone()).await.unwrap()
+    );
+    assert_eq!(
+        Some(binary_value.clone()),
+        kaference_2
+            .typed_query(BytesCodec, BytesCodec)
+            .local_get(&binary_key)
+            .unwrap()
+    );
+    assert_eq!(
+        vec![(binary_key.clone(), binary_value)],
+        query_1.prefix_scan(vec![0xff, 0x00]).await.unwrap()
+    );
+
+    // Typed values are encoded by the codec of the producer and decoded by the one of the query
+    let order = Order {
+        id: "order-1".to_string(),
+        amount: 42,
+        tags: vec!["express".to_string()],
+    };
+    let json_key = key_for_partition("json_order", proxy_partition, 2);
+    kaference_1
+        .typed_producer(BytesCodec, JsonCodec)
+        .unwrap()
+        .strong_consistency(topic, json_key.clone(), order.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        Some(order.clone()),
+        kaference_1
+            .typed_query(BytesCodec, JsonCodec)
+            .get(json_key.clone())
+            .await
+            .unwrap()
+    );
+    assert_eq!(
+        Some(serde_json::to_vec(&order).unwrap()),
+        kaference_1.query().get(json_key).await.unwrap()
+    );
+
+    let cbor_key = key_for_partition("cbor_order", proxy_partition, 2);
+    kaference_1
+        .typed_producer(BytesCodec, CborCodec)
+        .unwrap()
+        .strong_consistency(topic, cbor_key.clone(), order.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        Some(order),
+        kaference_1
+            .typed_query(BytesCodec, CborCodec)
+            .get(cbor_key)
+            .await
+            .unwrap()
+    );
+
+    service_1.abort();
+    service_2.abort();
+}
+
+#[tokio::test]
+async fn in_process_conditional_writes() {
+    let topic = "carts";
+    let transport = InProcessTransport::new();
+    transport.create_topic(topic, 2, false).await.unwrap();
+
+    let service_1_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let service_1_addr = service_1_listener.local_addr().unwrap();
+    let service_1_url = format!("http://{}", service_1_addr);
+    let kaference_1 = Kafence::new()
+        .with_topic(topic)
+        .with_consumer_group("carts-service")
+        .with_partitions(2)
+        .with_state_store(InMemoryStore::new())
+        .with_transport(transport.clone())
+        .with_service_url(&service_1_url)
+        .build();
+    let service_1 = tokio::spawn(run_server(service_1_listener, Arc::clone(&kaference_1)));
+
+    let service_2_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let service_2_url = format!("http://{}", service_2_listener.local_addr().unwrap());
+    let kaference_2 = Kafence::new()
+        .with_topic(topic)
+        .with_consumer_group("carts-service")
+        .with_partitions(2)
+        .with_state_store(InMemoryStore::new())
+        .with_transport(transport.clone())
+        .with_service_url(&service_2_url)
+        .build();
+    let service_2 = tokio::spawn(run_server(service_2_listener, Arc::clone(&kaference_2)));
+
+    wait_for_route_owners(&kaference_1, 2).await;
+    wait_for_route_owners(&kaference_2, 2).await;
+
+    let producer = kaference_1.producer().unwrap();
+    let query = kaference_1.query();
+    let local_partition = partition_owned_by(&kaference_1, &service_1_url);
+    let proxy_partition = partition_not_owned_by(&kaference_1, &service_1_url);
+    for partition in [local_partition, proxy_partition] {
+        // Versions, checked locally and by the owner of proxied writes
+        let key = key_for_partition("cart", partition, 2);
+        producer
+            .strong_consistency_if(topic, key.clone(), None, b"empty".to_vec())
+            .await
+            .unwrap();
+        let (value, version) = query.get_with_version(key.clone()).await.unwrap().unwrap();
+        assert_eq!(b"empty".to_vec(), value);
+        assert!(version > 0);
+
+        let error = producer
+            .strong_consistency_if(topic, key.clone(), None, b"lost".to_vec())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            KafenceErrorCode::ConditionFailed,
+            KafenceError::from_anyhow(error).code
+        );
+        producer
+            .strong_consistency_if(topic, key.clone(), Some(version), b"one item".to_vec())
+            .await
+            .unwrap();
+        let error = producer
+            .strong_consistency_if(topic, key.clone(), Some(version), b"lost".to_vec())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            KafenceErrorCode::ConditionFailed,
+            KafenceError::from_anyhow(error).code
+        );
+        let (value, next_version) = query.get_with_version(key.clone()).await.unwrap().unwrap();
+        assert_eq!(b"one item".to_vec(), value);
+        assert!(next_version > version);
+
+        // Values
+        producer
+            .compare_and_set(
+                topic,
+                key.clone(),
+                Some(b"one item".to_vec()),
+                b"two items".to_vec(),
+            )
+            .await
+            .unwrap();
+        let error = producer
+            .compare_and_set(
+                topic,
+                key.clone(),
+                Some(b"one item".to_vec()),
+                b"lost".to_vec(),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            KafenceErrorCode::ConditionFailed,
+            KafenceError::from_anyhow(error).code
+        );
+        assert_eq!(
+            Some(b"two items".to_vec()),
+            query.get(key.clone()).await.unwrap()
+        );
+    }
+
+    // Conditions sent to the router by clients
+    let client = Client::new();
+    let key = key_for_partition("cart", proxy_partition, 2);
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(format!("http://{service_1_addr}/records"))
+        .header("record-key", &key)
+        .header("x-kafence-if-version", "absent")
+        .body(Body::from("lost"))
+        .unwrap();
+    let response = client.request(request).await.unwrap();
+    assert_eq!(StatusCode::PRECONDITION_FAILED, response.status());
+    assert_eq!("condition_failed", response.headers()["x-kafence-error"]);
+
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri(format!("http://{service_1_addr}/records"))
+        .header("record-key", &key)
+        .body(Body::empty())
+        .unwrap();
+    let response = client.request(request).await.unwrap();
+    let version = response.headers()["x-kafence-version"].to_str().unwrap();
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(format!("http://{service_1_addr}/records"))
+        .header("record-key", &key)
+        .header("x-kafence-if-version", version)
+        .body(Body::from("three items"))
+        .unwrap();
+    let response = client.request(request).await.unwrap();
+    assert_eq!(StatusCode::ACCEPTED, response.status());
+    assert_eq!(
+        Some("three items".to_string()),
+        get_record(&client, service_1_addr, &key).await
+    );
+
+    // Keys of the offsets and versions kafence stores next to the records cannot be written
+    let (_, version) = query.get_with_version(key.clone()).await.unwrap().unwrap();
+    let reserved_key = format!("\0kafence:version:{key}");
+    let error = producer
+        .strong_consistency(topic, reserved_key.clone(), b"0".to_vec())
+        .await
+        .unwrap_err();
+    assert_eq!(
+        KafenceErrorCode::BadRequest,
+        KafenceError::from_anyhow(error).code
+    );
+    for proxied in [false, true] {
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri(format!("http://{service_1_addr}/records"))
+            .header("record-key", BASE64_STANDARD.encode(&reserved_key))
+            .header("record-key-encoding", "base64");
+        if proxied {
+            request = request.header("x-kafence-proxied", "true");
         }
+        let response = client
+            .request(request.body(Body::from("0")).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        assert_eq!("bad_request", response.headers()["x-kafence-error"]);
+    }
+    let (_, unchanged_version) = query.get_with_version(key.clone()).await.unwrap().unwrap();
+    assert_eq!(version, unchanged_version);
+
+    service_1.abort();
+    service_2.abort();
+}
+
+#[tokio::test]
+async fn in_process_rebalance() {
+    let transport = InProcessTransport::new();
+    transport.create_topic("orders", 2, false).await.unwrap();
+
+    let (assignments_1, listener_1) = assignment_recorder();
+    let consumer_1 = transport
+        .subscribe("client-1", "orders-service", "orders", listener_1)
+        .unwrap();
+    assert_eq!(HashSet::from([0, 1]), last_assignment(&assignments_1));
+
+    let (assignments_2, listener_2) = assignment_recorder();
+    let consumer_2 = transport
+        .subscribe("client-2", "orders-service", "orders", listener_2)
+        .unwrap();
+    assert_eq!(HashSet::from([0]), last_assignment(&assignments_1));
+    assert_eq!(HashSet::from([1]), last_assignment(&assignments_2));
+
+    transport
+        .send("orders", Some(1), b"order-1", Some(b"created"))
+        .await
+        .unwrap();
+    let record = consumer_2.recv().await.unwrap();
+    assert_eq!(Some(b"order-1".to_vec()), record.key);
+    consumer_2.commit(&record).unwrap();
+
+    drop(consumer_2);
+    assert_eq!(
+        (HashSet::from([0, 1]), 3),
+        last_generation_assignment(&assignments_1)
+    );
+
+    transport
+        .send("orders", Some(1), b"order-2", Some(b"paid"))
+        .await
+        .unwrap();
+    let record = consumer_1.recv().await.unwrap();
+    assert_eq!(Some(b"order-2".to_vec()), record.key);
+    assert_eq!(1, record.offset);
+}
+
+#[tokio::test]
+async fn in_process_route_publishing_retries() {
+    let transport = Arc::new(InProcessTransport::new());
+    let (sender, recv) = tokio::sync::mpsc::unbounded_channel();
+    let publishing_transport = Arc::clone(&transport);
+    let publishing = tokio::spawn(async move {
+        crate::publish_route_info(publishing_transport.as_ref(), "orders_router", recv).await;
+    });
+    sender
+        .send(crate::RouteInfo {
+            paritions: Arc::new(std::sync::RwLock::new(HashSet::from([1]))),
+            service_host: "http://service-1".to_string(),
+            epoch: 2,
+        })
+        .unwrap();
+
+    // The route topic does not exist yet, so the first delivery fails and is retried
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    transport
+        .create_topic("orders_router", 1, true)
+        .await
+        .unwrap();
+    let consumer = transport
+        .subscribe(
+            "client-1",
+            "orders-router",
+            "orders_router",
+            Arc::new(|_, _| {}),
+        )
+        .unwrap();
+    let record = consumer.recv().await.unwrap();
+    assert_eq!(Some(b"orders_router:1".to_vec()), record.key);
+    assert_eq!(
+        RouteEntry {
+            host: "http://service-1".to_string(),
+            epoch: 2
+        },
+        serde_json::from_slice(&record.payload.unwrap()).unwrap()
+    );
+
+    drop(sender);
+    publishing.await.unwrap();
+}
+
+type Assignments = Arc<Mutex<Vec<(HashSet<i32>, u64)>>>;
+
+fn assignment_recorder() -> (Assignments, RebalanceListener) {
+    let assignments = Arc::new(Mutex::new(vec![]));
+    let recorded = Arc::clone(&assignments);
+    let listener: RebalanceListener = Arc::new(move |partitions, generation| {
+        recorded.lock().unwrap().push((partitions, generation))
+    });
+    (assignments, listener)
+}
+
+fn last_assignment(assignments: &Assignments) -> HashSet<i32> {
+    last_generation_assignment(assignments).0
+}
+
+fn last_generation_assignment(assignments: &Assignments) -> (HashSet<i32>, u64) {
+    assignments
+        .lock()
+        .unwrap()
+        .last()
+        .cloned()
+        .unwrap_or_default()
+}
+
+#[test]
+fn route_table_fencing() {
+    let kafence = Kafence::new().with_topic("orders");
+    let route = |host: &str, epoch: u64| TransportRecord {
+        topic: kafence.topic_router.clone(),
+        partition: 0,
+        offset: 0,
+        key: Some(b"orders_router:1".to_vec()),
+        payload: Some(
+            serde_json::to_vec(&RouteEntry {
+                host: host.to_string(),
+                epoch,
+            })
+            .unwrap(),
+        ),
     };
-    Ok(response)
+    let owner = |kafence: &Kafence| kafence.route_table.read().unwrap()["orders_router:1"].clone();
+
+    crate::materialize_route_table(
+        "client",
+        &route("http://service-1", 2),
+        &kafence.route_table,
+    )
+    .unwrap();
+    crate::materialize_route_table(
+        "client",
+        &route("http://service-2", 1),
+        &kafence.route_table,
+    )
+    .unwrap();
+    assert_eq!(
+        RouteEntry {
+            host: "http://service-1".to_string(),
+            epoch: 2
+        },
+        owner(&kafence)
+    );
+
+    crate::materialize_route_table(
+        "client",
+        &route("http://service-2", 3),
+        &kafence.route_table,
+    )
+    .unwrap();
+    assert_eq!("http://service-2", owner(&kafence).host);
+
+    crate::materialize_route_table("client", &route("", 2), &kafence.route_table).unwrap();
+    assert_eq!("http://service-2", owner(&kafence).host);
+    crate::materialize_route_table("client", &route("", 3), &kafence.route_table).unwrap();
+    assert!(
+        !kafence
+            .route_table
+            .read()
+            .unwrap()
+            .contains_key("orders_router:1")
+    );
+}
+
+#[test]
+fn partition_ownership_epochs() {
+    let kafence = Kafence::new()
+        .with_topic("orders")
+        .with_service_url("http://service-1");
+    *kafence.assignment.write().unwrap() = PartitionAssignment {
+        partitions: HashSet::from([1]),
+        epoch: 2,
+    };
+    let query = kafence.query();
+    assert_eq!(Ok(2), query.owned_epoch(1, None));
+    assert_eq!(Ok(2), query.owned_epoch(1, Some(2)));
+    assert_eq!(
+        KafenceErrorCode::NotOwner,
+        query.owned_epoch(0, None).unwrap_err().code
+    );
+    assert_eq!(
+        KafenceErrorCode::NotOwner,
+        query.owned_epoch(1, Some(3)).unwrap_err().code
+    );
+
+    kafence.route_table.write().unwrap().insert(
+        "orders_router:1".to_string(),
+        RouteEntry {
+            host: "http://service-2".to_string(),
+            epoch: 3,
+        },
+    );
+    assert_eq!(
+        KafenceErrorCode::NotOwner,
+        query.owned_epoch(1, None).unwrap_err().code
+    );
+}
+
+#[test]
+fn scan_entries_encoding() {
+    let entries = vec![
+        (b"order-1".to_vec(), b"created".to_vec()),
+        (b"order-2".to_vec(), vec![]),
+    ];
+    let bytes = crate::encode_entries(&entries);
+    assert_eq!(entries, crate::decode_entries(&bytes).unwrap());
+    assert!(crate::decode_entries(&bytes[..bytes.len() - 1]).is_err());
+    assert!(crate::decode_entries(&[0, 0]).is_err());
+
+    let keys = vec![b"order-1".to_vec(), vec![]];
+    assert_eq!(
+        keys,
+        crate::decode_fields(&crate::encode_fields(&keys)).unwrap()
+    );
+
+    let partitions = vec![(0, 3), (2, 4)];
+    let routed = crate::encode_routed_partitions(&partitions);
+    assert_eq!("0:3,2:4", routed);
+    assert_eq!(
+        partitions,
+        crate::decode_routed_partitions(&routed).unwrap()
+    );
+    assert!(crate::decode_routed_partitions("0").is_err());
+}
+
+#[test]
+fn codecs() {
+    let order = Order {
+        id: "order-1".to_string(),
+        amount: 42,
+        tags: vec!["express".to_string(), "gift".to_string()],
+    };
+    let json = Codec::<Order>::encode(&JsonCodec, &order).unwrap();
+    assert_eq!(
+        br#"{"id":"order-1","amount":42,"tags":["express","gift"]}"#.to_vec(),
+        json
+    );
+    assert_eq!(order, JsonCodec.decode(&json).unwrap());
+
+    let cbor = Codec::<Order>::encode(&CborCodec, &order).unwrap();
+    assert_eq!(order, CborCodec.decode(&cbor).unwrap());
+    assert!(Codec::<Order>::decode(&CborCodec, &json).is_err());
+
+    let bytes = vec![0xff, 0x00, 0x80];
+    assert_eq!(bytes, BytesCodec.encode(&bytes).unwrap());
+    assert_eq!(
+        bytes,
+        Codec::<Vec<u8>>::decode(&BytesCodec, &bytes).unwrap()
+    );
+    assert_eq!(
+        b"order-1".to_vec(),
+        BytesCodec.encode(&"order-1".to_string()).unwrap()
+    );
+    assert!(Codec::<String>::decode(&BytesCodec, &bytes).is_err());
+}
+
+#[test]
+fn in_memory_store() {
+    assert_state_store(&InMemoryStore::new());
+}
+
+#[test]
+fn rocksdb_store() {
+    let rocksdb_path = std::env::temp_dir()
+        .join(format!("kafence-store-{}", Uuid::new_v4()))
+        .to_string_lossy()
+        .into_owned();
+    let state_store = RocksDbStore::open(&rocksdb_path).unwrap();
+    assert_state_store(&state_store);
+
+    state_store.close().unwrap();
+    assert!(state_store.get(b"order-2").is_err());
+    assert_eq!(
+        Some(b"paid".to_vec()),
+        RocksDbStore::open(&rocksdb_path)
+            .unwrap()
+            .get(b"order-2")
+            .unwrap()
+    );
+}
+
+fn assert_state_store(state_store: &dyn StateStore) {
+    state_store.put(b"order-2", b"paid").unwrap();
+    state_store.put(b"order-1", b"created").unwrap();
+    state_store.put(b"payment-1", b"settled").unwrap();
+    assert_eq!(
+        Some(b"created".to_vec()),
+        state_store.get(b"order-1").unwrap()
+    );
+    assert_eq!(None, state_store.get(b"order-3").unwrap());
+    assert_eq!(
+        vec![
+            (b"order-1".to_vec(), b"created".to_vec()),
+            (b"order-2".to_vec(), b"paid".to_vec()),
+        ],
+        state_store.scan(b"order-").unwrap()
+    );
+
+    state_store.delete(b"order-1").unwrap();
+    assert_eq!(None, state_store.get(b"order-1").unwrap());
+    assert_eq!(1, state_store.scan(b"order-").unwrap().len());
+    state_store.flush().unwrap();
+}
+
+pub async fn run_server(listener: TcpListener, kafence: Arc<Kafence>) {
+    println!("Preparing Service...");
+    kafence.stream().await.unwrap();
+    if let Err(e) = kafence.router().unwrap().serve(listener).await {
+        println!("server error: {}", e);
+    }
 }
 
 async fn post_record(
@@ -225,7 +1203,7 @@ async fn post_record(
 ) -> Response<Body> {
     let request = Request::builder()
         .method(Method::POST)
-        .uri(format!("http://{addr}/"))
+        .uri(format!("http://{addr}/records"))
         .header("record-key", key)
         .body(Body::from(value.to_string()))
         .unwrap();
@@ -233,6 +1211,61 @@ async fn post_record(
     client.request(request).await.unwrap()
 }
 
+async fn get_record(
+    client: &Client<hyper::client::HttpConnector>,
+    addr: std::net::SocketAddr,
+    key: &str,
+) -> Option<String> {
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri(format!("http://{addr}/records"))
+        .header("record-key", key)
+        .body(Body::empty())
+        .unwrap();
+
+    let response = client.request(request).await.unwrap();
+    match response.status() {
+        StatusCode::NOT_FOUND => None,
+        StatusCode::OK => {
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            Some(String::from_utf8_lossy(&body).into_owned())
+        }
+        status => panic!("unexpected read status {status}"),
+    }
+}
+
+async fn get_metrics(
+    client: &Client<hyper::client::HttpConnector>,
+    addr: std::net::SocketAddr,
+) -> String {
+    let response = client
+        .get(format!("http://{addr}/metrics").parse().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+/// Point the route of the partition to another instance, with the same epoch, until it is fixed
+/// 300 ms later, so requests are rejected by that instance and retried.
+fn stale_route_for(kafence: &Arc<Kafence>, partition: i32, stale_host: &str) {
+    let route_key = format!("{}:{}", kafence.topic_router, partition);
+    let route = kafence.route_table.read().unwrap()[&route_key].clone();
+    kafence.route_table.write().unwrap().insert(
+        route_key.clone(),
+        RouteEntry {
+            host: stale_host.to_string(),
+            epoch: route.epoch,
+        },
+    );
+    let route_table = Arc::clone(&kafence.route_table);
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        route_table.write().unwrap().insert(route_key, route);
+    });
+}
+
 async fn wait_for_routes(kafence: &Arc<Kafence>, expected_routes: usize) {
     for _ in 0..100 {
         let route_count = kafence.route_table.read().unwrap().len();
@@ -248,6 +1281,24 @@ async fn wait_for_routes(kafence: &Arc<Kafence>, expected_routes: usize) {
     );
 }
 
+async fn wait_for_route_owners(kafence: &Arc<Kafence>, expected_owners: usize) {
+    for _ in 0..100 {
+        let owners = kafence
+            .route_table
+            .read()
+            .unwrap()
+            .values()
+            .map(|route| route.host.clone())
+            .collect::<HashSet<_>>();
+        if owners.len() >= expected_owners {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    panic!("route table was not balanced. expected_owners={expected_owners}");
+}
+
 fn partition_owned_by(kafence: &Kafence, service_url: &str) -> i32 {
     (0..kafence.partitions)
         .find(|partition| route_owner(kafence, *partition) == service_url)
@@ -267,7 +1318,7 @@ fn route_owner(kafence: &Kafence, partition: i32) -> String {
         .read()
         .unwrap()
         .get(&route_key)
-        .cloned()
+        .map(|route| route.host.clone())
         .expect("route must exist")
 }
 
@@ -282,6 +1333,17 @@ fn key_for_partition(prefix: &str, target_partition: i32, partition_count: i32)
     panic!("could not find key for partition {target_partition}");
 }
 
+fn binary_key_for_partition(target_partition: i32, partition_count: i32) -> Vec<u8> {
+    for attempt in 0..10_000u32 {
+        let key = [&[0xff, 0x00][..], &attempt.to_be_bytes()].concat();
+        if crate::partition_for_key(&key, partition_count) == target_partition {
+            return key;
+        }
+    }
+
+    panic!("could not find binary key for partition {target_partition}");
+}
+
 async fn create_topic_if_not_exists(
     brokers: &str,
     topic: &str,
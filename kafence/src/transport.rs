@@ -0,0 +1,572 @@
+use crate::partition_for_key;
+use anyhow::Result;
+use rdkafka::admin::{AdminClient, AdminOptions, NewTopic, TopicReplication};
+use rdkafka::bindings::{
+    rd_kafka_consumer_group_metadata, rd_kafka_consumer_group_metadata_destroy,
+    rd_kafka_consumer_group_metadata_generation_id,
+};
+use rdkafka::client::{ClientContext, DefaultClientContext};
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{
+    BaseConsumer, CommitMode, Consumer, ConsumerContext, Rebalance, StreamConsumer,
+};
+use rdkafka::error::RDKafkaErrorCode;
+use rdkafka::message::Message;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::{Offset, TopicPartitionList};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tracing::{debug, info, warn};
+
+pub(crate) type TransportFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+/// Invoked with the partitions of the subscribed topic assigned to the consumer, after every rebalance,
+/// along with the generation of the consumer group, that grows with every rebalance.
+pub(crate) type RebalanceListener = Arc<dyn Fn(HashSet<i32>, u64) + Send + Sync>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct TransportRecord {
+    pub(crate) topic: String,
+    pub(crate) partition: i32,
+    pub(crate) offset: i64,
+    pub(crate) key: Option<Vec<u8>>,
+    pub(crate) payload: Option<Vec<u8>>,
+}
+
+/// Log the streams are built on. Producing, consuming with a consumer group, committing offsets,
+/// creating topics and rebalance notifications all go through it.
+pub(crate) trait Transport: Send + Sync {
+    fn create_topic<'a>(
+        &'a self,
+        topic: &'a str,
+        partitions: i32,
+        compacted: bool,
+    ) -> TransportFuture<'a, ()>;
+
+    /// Without `partition`, the partition is chosen from the key with `partition_for_key`.
+    /// A record without payload is a tombstone.
+    fn send<'a>(
+        &'a self,
+        topic: &'a str,
+        partition: Option<i32>,
+        key: &'a [u8],
+        payload: Option<&'a [u8]>,
+    ) -> TransportFuture<'a, ()>;
+
+    fn subscribe(
+        &self,
+        client_id: &str,
+        group_id: &str,
+        topic: &str,
+        on_rebalance: RebalanceListener,
+    ) -> Result<Box<dyn TransportConsumer>>;
+}
+
+pub(crate) trait TransportConsumer: Send + Sync {
+    fn recv(&self) -> TransportFuture<'_, TransportRecord>;
+
+    fn commit(&self, record: &TransportRecord) -> Result<()>;
+
+    /// Commit the offset of the record, and wait until the commit is acknowledged.
+    fn commit_sync(&self, record: &TransportRecord) -> Result<()>;
+
+    /// Consume an assigned partition from `offset` on, instead of the committed offset of the group.
+    fn seek(&self, partition: i32, offset: i64) -> Result<()>;
+
+    /// Offset the next record appended to the partition will get.
+    fn end_offset(&self, partition: i32) -> Result<i64>;
+}
+
+// Kafka Transport
+// ---------------
+
+pub(crate) struct KafkaTransport {
+    brokers: String,
+    producer: FutureProducer,
+}
+
+impl KafkaTransport {
+    pub(crate) fn new(brokers: &str) -> Result<KafkaTransport> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("message.timeout.ms", "5000")
+            .create()?;
+        Ok(KafkaTransport {
+            brokers: brokers.to_string(),
+            producer,
+        })
+    }
+}
+
+struct KafkaRebalanceContext {
+    topic: String,
+    on_rebalance: RebalanceListener,
+}
+
+impl ClientContext for KafkaRebalanceContext {}
+
+impl ConsumerContext for KafkaRebalanceContext {
+    fn post_rebalance(&self, consumer: &BaseConsumer<Self>, rebalance: &Rebalance<'_>) {
+        info!(rebalance = ?rebalance, "rebalanced");
+        match rebalance {
+            Rebalance::Assign(_) | Rebalance::Revoke(_) => {
+                let partitions = consumer
+                    .assignment()
+                    .map(|tp_list| {
+                        tp_list
+                            .elements()
+                            .iter()
+                            .filter(|tp| tp.topic() == self.topic)
+                            .map(|tp| {
+                                debug!(
+                                    topic = tp.topic(),
+                                    partition = tp.partition(),
+                                    "partition assigned"
+                                );
+                                tp.partition()
+                            })
+                            .collect::<HashSet<_>>()
+                    })
+                    .unwrap_or_default();
+                (self.on_rebalance)(partitions, group_generation(consumer));
+            }
+            Rebalance::Error(e) => {
+                warn!(error = %e, "rebalance failed");
+            }
+        }
+    }
+}
+
+fn group_generation(consumer: &BaseConsumer<KafkaRebalanceContext>) -> u64 {
+    unsafe {
+        let metadata = rd_kafka_consumer_group_metadata(consumer.client().native_ptr());
+        if metadata.is_null() {
+            return 0;
+        }
+        let generation = rd_kafka_consumer_group_metadata_generation_id(metadata);
+        rd_kafka_consumer_group_metadata_destroy(metadata);
+        u64::try_from(generation).unwrap_or_default()
+    }
+}
+
+struct KafkaConsumer {
+    topic: String,
+    consumer: StreamConsumer<KafkaRebalanceContext>,
+}
+
+impl Transport for KafkaTransport {
+    fn create_topic<'a>(
+        &'a self,
+        topic: &'a str,
+        partitions: i32,
+        compacted: bool,
+    ) -> TransportFuture<'a, ()> {
+        Box::pin(async move {
+            let admin: AdminClient<DefaultClientContext> = ClientConfig::new()
+                .set("bootstrap.servers", &self.brokers)
+                .set("session.timeout.ms", "6000")
+                .create()?;
+
+            //TODO:Make replication factor configurable
+            let mut new_topic = NewTopic::new(topic, partitions, TopicReplication::Fixed(1));
+            if compacted {
+                new_topic = new_topic.set("cleanup.policy", "compact");
+            }
+            let admin_options =
+                AdminOptions::new().operation_timeout(Some(Duration::from_secs(10)));
+
+            for result in admin.create_topics(&[new_topic], &admin_options).await? {
+                match result {
+                    Ok(name) => {
+                        info!(topic = %name, "created topic")
+                    }
+                    Err((_, RDKafkaErrorCode::TopicAlreadyExists)) => {}
+                    Err((name, code)) => {
+                        anyhow::bail!("error creating topic {} with error {}", name, code)
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn send<'a>(
+        &'a self,
+        topic: &'a str,
+        partition: Option<i32>,
+        key: &'a [u8],
+        payload: Option<&'a [u8]>,
+    ) -> TransportFuture<'a, ()> {
+        Box::pin(async move {
+            let mut record = FutureRecord::<[u8], [u8]>::to(topic).key(key);
+            if let Some(partition) = partition {
+                record = record.partition(partition);
+            }
+            if let Some(payload) = payload {
+                record = record.payload(payload);
+            }
+            match self.producer.send(record, Duration::from_secs(5)).await {
+                Ok(_) => Ok(()),
+                Err((e, _)) => anyhow::bail!("record delivery failed: {e}"),
+            }
+        })
+    }
+
+    fn subscribe(
+        &self,
+        client_id: &str,
+        group_id: &str,
+        topic: &str,
+        on_rebalance: RebalanceListener,
+    ) -> Result<Box<dyn TransportConsumer>> {
+        let context = KafkaRebalanceContext {
+            topic: topic.to_string(),
+            on_rebalance,
+        };
+        let consumer: StreamConsumer<KafkaRebalanceContext> = ClientConfig::new()
+            .set("bootstrap.servers", &self.brokers)
+            .set("group.id", group_id)
+            .set("client.id", client_id)
+            .set("enable.auto.commit", "false")
+            .set("auto.offset.reset", "earliest")
+            .set("session.timeout.ms", "6000")
+            .create_with_context(context)?;
+        consumer.subscribe(&[topic])?;
+        Ok(Box::new(KafkaConsumer {
+            topic: topic.to_string(),
+            consumer,
+        }))
+    }
+}
+
+impl KafkaConsumer {
+    fn commit_offset(&self, record: &TransportRecord, mode: CommitMode) -> Result<()> {
+        let mut offsets = TopicPartitionList::new();
+        offsets.add_partition_offset(
+            &record.topic,
+            record.partition,
+            Offset::Offset(record.offset + 1),
+        )?;
+        self.consumer.commit(&offsets, mode)?;
+        Ok(())
+    }
+}
+
+impl TransportConsumer for KafkaConsumer {
+    fn recv(&self) -> TransportFuture<'_, TransportRecord> {
+        Box::pin(async move {
+            let message = self.consumer.recv().await?;
+            Ok(TransportRecord {
+                topic: message.topic().to_string(),
+                partition: message.partition(),
+                offset: message.offset(),
+                key: message.key().map(<[u8]>::to_vec),
+                payload: message.payload().map(<[u8]>::to_vec),
+            })
+        })
+    }
+
+    fn commit(&self, record: &TransportRecord) -> Result<()> {
+        self.commit_offset(record, CommitMode::Async)
+    }
+
+    fn commit_sync(&self, record: &TransportRecord) -> Result<()> {
+        self.commit_offset(record, CommitMode::Sync)
+    }
+
+    fn seek(&self, partition: i32, offset: i64) -> Result<()> {
+        self.consumer.seek(
+            &self.topic,
+            partition,
+            Offset::Offset(offset),
+            Duration::from_secs(5),
+        )?;
+        Ok(())
+    }
+
+    fn end_offset(&self, partition: i32) -> Result<i64> {
+        let (_, high) =
+            self.consumer
+                .fetch_watermarks(&self.topic, partition, Duration::from_secs(5))?;
+        Ok(high)
+    }
+}
+
+// In Process Transport
+// --------------------
+
+/// Partitioned log kept in the process memory, so the streams can run without a broker.
+/// Consumers of the same group share the partitions of the topic, and every time a consumer
+/// joins or is dropped the partitions are assigned again and the listeners notified, as Kafka
+/// does on a rebalance. Assigned partitions are consumed from the committed offset of the group.
+#[derive(Clone, Default)]
+pub(crate) struct InProcessTransport {
+    log: Arc<Mutex<InProcessLog>>,
+    appended: Arc<Notify>,
+}
+
+#[derive(Default)]
+struct InProcessLog {
+    topics: HashMap<String, Vec<Vec<TransportRecord>>>,
+    groups: HashMap<(String, String), InProcessGroup>,
+    next_member_id: u64,
+}
+
+#[derive(Default)]
+struct InProcessGroup {
+    generation: u64,
+    members: Vec<InProcessMember>,
+    committed: HashMap<i32, i64>,
+}
+
+struct InProcessMember {
+    id: u64,
+    on_rebalance: RebalanceListener,
+    positions: HashMap<i32, i64>,
+}
+
+struct InProcessConsumer {
+    transport: InProcessTransport,
+    group: (String, String),
+    member_id: u64,
+}
+
+impl InProcessTransport {
+    pub(crate) fn new() -> InProcessTransport {
+        InProcessTransport::default()
+    }
+
+    /// Start a new generation of the group, assigning the partitions in ranges, in the order the
+    /// members joined, and notify every member with its new assignment once the log is released.
+    fn rebalance(&self, group: &(String, String)) {
+        let (generation, notifications) = {
+            let mut log = self.log.lock().unwrap();
+            let partitions = log.topics.get(&group.1).map_or(0, Vec::len) as i32;
+            let Some(state) = log.groups.get_mut(group) else {
+                return;
+            };
+            state.generation += 1;
+            let generation = state.generation;
+            let members = state.members.len() as i32;
+            let committed = state.committed.clone();
+            let notifications = state
+                .members
+                .iter_mut()
+                .enumerate()
+                .map(|(index, member)| {
+                    let index = index as i32;
+                    let assigned = (0..partitions)
+                        .filter(|partition| partition * members / partitions.max(1) == index)
+                        .collect::<HashSet<_>>();
+                    member.positions = assigned
+                        .iter()
+                        .map(|partition| {
+                            let offset = member
+                                .positions
+                                .get(partition)
+                                .or_else(|| committed.get(partition))
+                                .copied()
+                                .unwrap_or(0);
+                            (*partition, offset)
+                        })
+                        .collect();
+                    (member.on_rebalance.clone(), assigned)
+                })
+                .collect::<Vec<_>>();
+            (generation, notifications)
+        };
+        for (on_rebalance, assigned) in notifications {
+            on_rebalance(assigned, generation);
+        }
+        self.appended.notify_waiters();
+    }
+}
+
+impl Transport for InProcessTransport {
+    fn create_topic<'a>(
+        &'a self,
+        topic: &'a str,
+        partitions: i32,
+        _compacted: bool,
+    ) -> TransportFuture<'a, ()> {
+        Box::pin(async move {
+            if partitions <= 0 {
+                anyhow::bail!("topic partitions must be greater than zero");
+            }
+            let mut log = self.log.lock().unwrap();
+            if !log.topics.contains_key(topic) {
+                info!(topic = %topic, "created topic");
+                log.topics
+                    .insert(topic.to_string(), vec![vec![]; partitions as usize]);
+            }
+            Ok(())
+        })
+    }
+
+    fn send<'a>(
+        &'a self,
+        topic: &'a str,
+        partition: Option<i32>,
+        key: &'a [u8],
+        payload: Option<&'a [u8]>,
+    ) -> TransportFuture<'a, ()> {
+        Box::pin(async move {
+            {
+                let mut log = self.log.lock().unwrap();
+                let Some(partitions) = log.topics.get_mut(topic) else {
+                    anyhow::bail!("record delivery failed: unknown topic {topic}");
+                };
+                let partition =
+                    partition.unwrap_or_else(|| partition_for_key(key, partitions.len() as i32));
+                let Some(records) = partitions.get_mut(partition as usize) else {
+                    anyhow::bail!("record delivery failed: unknown partition {topic}:{partition}");
+                };
+                records.push(TransportRecord {
+                    topic: topic.to_string(),
+                    partition,
+                    offset: records.len() as i64,
+                    key: Some(key.to_vec()),
+                    payload: payload.map(<[u8]>::to_vec),
+                });
+            }
+            self.appended.notify_waiters();
+            Ok(())
+        })
+    }
+
+    fn subscribe(
+        &self,
+        _client_id: &str,
+        group_id: &str,
+        topic: &str,
+        on_rebalance: RebalanceListener,
+    ) -> Result<Box<dyn TransportConsumer>> {
+        let group = (group_id.to_string(), topic.to_string());
+        let member_id = {
+            let mut log = self.log.lock().unwrap();
+            if !log.topics.contains_key(topic) {
+                anyhow::bail!("unknown topic {topic}");
+            }
+            log.next_member_id += 1;
+            let member_id = log.next_member_id;
+            log.groups
+                .entry(group.clone())
+                .or_default()
+                .members
+                .push(InProcessMember {
+                    id: member_id,
+                    on_rebalance,
+                    positions: HashMap::new(),
+                });
+            member_id
+        };
+        self.rebalance(&group);
+        Ok(Box::new(InProcessConsumer {
+            transport: self.clone(),
+            group,
+            member_id,
+        }))
+    }
+}
+
+impl InProcessConsumer {
+    fn poll(&self) -> Option<TransportRecord> {
+        let mut log = self.transport.log.lock().unwrap();
+        let InProcessLog { topics, groups, .. } = &mut *log;
+        let records = topics.get(&self.group.1)?;
+        let member = groups
+            .get_mut(&self.group)?
+            .members
+            .iter_mut()
+            .find(|member| member.id == self.member_id)?;
+        let mut partitions = member.positions.keys().copied().collect::<Vec<_>>();
+        partitions.sort();
+        for partition in partitions {
+            let position = member.positions[&partition];
+            if let Some(record) = records[partition as usize].get(position as usize) {
+                member.positions.insert(partition, position + 1);
+                return Some(record.clone());
+            }
+        }
+        None
+    }
+}
+
+impl TransportConsumer for InProcessConsumer {
+    fn recv(&self) -> TransportFuture<'_, TransportRecord> {
+        Box::pin(async move {
+            loop {
+                let appended = self.transport.appended.notified();
+                if let Some(record) = self.poll() {
+                    return Ok(record);
+                }
+                appended.await;
+            }
+        })
+    }
+
+    fn commit(&self, record: &TransportRecord) -> Result<()> {
+        let mut log = self.transport.log.lock().unwrap();
+        if let Some(group) = log.groups.get_mut(&self.group) {
+            group.committed.insert(record.partition, record.offset + 1);
+        }
+        Ok(())
+    }
+
+    fn commit_sync(&self, record: &TransportRecord) -> Result<()> {
+        self.commit(record)
+    }
+
+    fn seek(&self, partition: i32, offset: i64) -> Result<()> {
+        {
+            let mut log = self.transport.log.lock().unwrap();
+            let member = log
+                .groups
+                .get_mut(&self.group)
+                .and_then(|group| {
+                    group
+                        .members
+                        .iter_mut()
+                        .find(|member| member.id == self.member_id)
+                })
+                .ok_or_else(|| anyhow::anyhow!("consumer left the group"))?;
+            let Some(position) = member.positions.get_mut(&partition) else {
+                anyhow::bail!("partition {}:{partition} is not assigned", self.group.1);
+            };
+            *position = offset;
+        }
+        self.transport.appended.notify_waiters();
+        Ok(())
+    }
+
+    fn end_offset(&self, partition: i32) -> Result<i64> {
+        let log = self.transport.log.lock().unwrap();
+        log.topics
+            .get(&self.group.1)
+            .and_then(|partitions| partitions.get(partition as usize))
+            .map(|records| records.len() as i64)
+            .ok_or_else(|| anyhow::anyhow!("unknown partition {}:{partition}", self.group.1))
+    }
+}
+
+impl Drop for InProcessConsumer {
+    fn drop(&mut self) {
+        let left = {
+            let mut log = self.transport.log.lock().unwrap();
+            match log.groups.get_mut(&self.group) {
+                Some(group) => {
+                    group.members.retain(|member| member.id != self.member_id);
+                    true
+                }
+                None => false,
+            }
+        };
+        if left {
+            self.transport.rebalance(&self.group);
+        }
+    }
+}
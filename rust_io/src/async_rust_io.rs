@@ -0,0 +1,486 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::future::{join_all, BoxFuture};
+use futures::FutureExt;
+
+use crate::{Lift, RustIO};
+use crate::settled::Settled;
+
+///Async version of [RustIO] backed by a [BoxFuture].
+/// Nothing runs until the program is awaited, and awaiting it resolves into a [RustIO].
+/// [delay] use a non-blocking timer, and [parallel] and [fork] spawn tasks in the tokio executor,
+/// so the program must be awaited inside a tokio runtime, but it can be built anywhere.
+/// It exposes the same combinators than [Lift] as its own functions, without implementing it:
+/// [get], [is_ok] and the other accessors of [Lift] need the result without awaiting it, and every
+/// function passed here must be [Send] and ['static] since it might run in another thread of the
+/// executor, while [Lift] functions have no such bounds.
+pub struct AsyncRustIO<A, T> {
+    program: BoxFuture<'static, Settled<A, T>>,
+}
+
+/// Convert a [RustIO] into an [AsyncRustIO]. A [Fut] value is joined first.
+impl<A: Send + 'static, T: Send + 'static> From<RustIO<A, T>> for AsyncRustIO<A, T> {
+    fn from(rio: RustIO<A, T>) -> Self {
+        AsyncRustIO::settled(Settled::from(rio))
+    }
+}
+
+/// Awaiting the program run all the steps, and return the result as [RustIO].
+impl<A, T> Future for AsyncRustIO<A, T> {
+    type Output = RustIO<A, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.program.as_mut().poll(cx).map(RustIO::from)
+    }
+}
+
+impl<A: Send + 'static, T: Send + 'static> AsyncRustIO<A, T> {
+    fn new<Fu: Future<Output=Settled<A, T>> + Send + 'static>(program: Fu) -> Self {
+        AsyncRustIO { program: program.boxed() }
+    }
+
+    fn settled(settled: Settled<A, T>) -> Self {
+        AsyncRustIO::new(async move { settled })
+    }
+
+    pub fn lift(a: A) -> Self {
+        AsyncRustIO::of(a)
+    }
+
+    /// Pure value to create AsyncRustIO monad without side-effects.
+    pub fn of(a: A) -> Self {
+        AsyncRustIO::settled(Settled::Value(a))
+    }
+
+    pub fn from_func(f: fn() -> A) -> Self {
+        AsyncRustIO::new(async move { Settled::Value(f()) })
+    }
+
+    pub fn from_option_func(f: fn() -> Option<A>) -> Self {
+        AsyncRustIO::new(async move { Settled::from(RustIO::from_option(f())) })
+    }
+
+    pub fn from_result_func(f: fn() -> Result<A, T>) -> Self {
+        AsyncRustIO::new(async move { Settled::from(RustIO::from_result(f())) })
+    }
+
+    pub fn from_option(a: Option<A>) -> Self {
+        AsyncRustIO::from(RustIO::from_option(a))
+    }
+
+    pub fn from_result(a: Result<A, T>) -> Self {
+        AsyncRustIO::from(RustIO::from_result(a))
+    }
+
+    /// Lift the output of a [Future] into the program.
+    pub fn from_future<Fu: Future<Output=A> + Send + 'static>(future: Fu) -> Self {
+        AsyncRustIO::new(async move { Settled::Value(future.await) })
+    }
+
+    /// Lift the [Result] of a [Future] into the program as [Right] or [Wrong].
+    pub fn from_future_result<Fu: Future<Output=Result<A, T>> + Send + 'static>(future: Fu) -> Self {
+        AsyncRustIO::new(async move { Settled::from(RustIO::from_result(future.await)) })
+    }
+
+    pub fn merge<F: FnOnce(A, A) -> Self + Send + 'static>(a: Self, b: Self, op: F) -> Self {
+        a.flat_map(move |x| b.flat_map(move |y| op(x, y)))
+    }
+
+    pub fn map<B: Send + 'static, F: FnOnce(A) -> B + Send + 'static>(self, op: F) -> AsyncRustIO<B, T> {
+        let program = self.program;
+        AsyncRustIO::new(async move {
+            match program.await {
+                Settled::Value(v) => Settled::Value(op(v)),
+                Settled::Right(v) => Settled::Right(op(v)),
+                Settled::Wrong(e) => Settled::Wrong(e),
+                Settled::Empty => Settled::Empty,
+            }
+        })
+    }
+
+    pub fn map_error<U: Send + 'static, F: FnOnce(T) -> U + Send + 'static>(self, op: F) -> AsyncRustIO<A, U> {
+        let program = self.program;
+        AsyncRustIO::new(async move {
+            match program.await {
+                Settled::Wrong(e) => Settled::Wrong(op(e)),
+                Settled::Value(v) => Settled::Value(v),
+                Settled::Right(v) => Settled::Right(v),
+                Settled::Empty => Settled::Empty,
+            }
+        })
+    }
+
+    pub fn flat_map<B: Send + 'static, F: FnOnce(A) -> AsyncRustIO<B, T> + Send + 'static>(self, op: F) -> AsyncRustIO<B, T> {
+        let program = self.program;
+        AsyncRustIO::new(async move {
+            let next = match program.await {
+                Settled::Value(a) | Settled::Right(a) => op(a),
+                Settled::Wrong(e) => AsyncRustIO::settled(Settled::Wrong(e)),
+                Settled::Empty => AsyncRustIO::settled(Settled::Empty),
+            };
+            next.program.await
+        })
+    }
+
+    ///Returns an effect that ignores errors and runs repeatedly until it [at_some_point] succeeds
+    pub fn at_some_point<F: FnOnce(A) -> Self + Clone + Send + 'static>(self, op: F) -> Self where A: Clone {
+        self.at_some_point_cond(None, || false, op)
+    }
+
+    /// Retry pattern of a task while a predicate condition is [false]
+    pub fn at_some_point_while<P: FnOnce() -> bool + Clone + Send + 'static, F: FnOnce(A) -> Self + Clone + Send + 'static>(self, predicate: P, op: F) -> Self where A: Clone {
+        self.at_some_point_cond(Some(false), predicate, op)
+    }
+
+    /// Retry pattern of a task while a predicate condition is [true]
+    pub fn at_some_point_until<P: FnOnce() -> bool + Clone + Send + 'static, F: FnOnce(A) -> Self + Clone + Send + 'static>(self, predicate: P, op: F) -> Self where A: Clone {
+        self.at_some_point_cond(Some(true), predicate, op)
+    }
+
+    pub fn when<P: FnOnce(&A) -> bool + Send + 'static, F: FnOnce(A) -> A + Send + 'static>(self, predicate: P, op: F) -> Self {
+        self.filter(predicate).map(op)
+    }
+
+    pub fn when_rio<P: FnOnce(&A) -> bool + Send + 'static, F: FnOnce(A) -> Self + Send + 'static>(self, predicate: P, op: F) -> Self {
+        self.filter(predicate).flat_map(op)
+    }
+
+    /// Run both programs concurrently, and combine their values once both succeed.
+    pub fn zip<B: Send + 'static, C: Send + 'static, Z1: FnOnce() -> Self, Z2: FnOnce() -> AsyncRustIO<B, T>, F: FnOnce(A, B) -> AsyncRustIO<C, T> + Send + 'static>(a: Z1, b: Z2, op: F) -> AsyncRustIO<C, T> {
+        let zip_1 = a().program;
+        let zip_2 = b().program;
+        AsyncRustIO::new(async move {
+            let next = match futures::join!(zip_1, zip_2) {
+                (Settled::Value(x) | Settled::Right(x), Settled::Value(y) | Settled::Right(y)) => op(x, y),
+                _ => AsyncRustIO::settled(Settled::Empty),
+            };
+            next.program.await
+        })
+    }
+
+    pub fn filter<F: FnOnce(&A) -> bool + Send + 'static>(self, op: F) -> Self {
+        let program = self.program;
+        AsyncRustIO::new(async move {
+            match program.await {
+                Settled::Value(v) => if op(&v) { Settled::Value(v) } else { Settled::Empty },
+                Settled::Right(v) => if op(&v) { Settled::Right(v) } else { Settled::Empty },
+                other => other,
+            }
+        })
+    }
+
+    pub fn fold<B: Send + 'static, F: FnOnce(A) -> B + Send + 'static>(self, default: B, op: F) -> AsyncRustIO<B, T> {
+        let program = self.program;
+        AsyncRustIO::new(async move {
+            match program.await {
+                Settled::Value(v) => Settled::Value(op(v)),
+                Settled::Right(v) => Settled::Right(op(v)),
+                Settled::Empty => Settled::Value(default),
+                Settled::Wrong(e) => Settled::Wrong(e),
+            }
+        })
+    }
+
+    pub fn recover<F: FnOnce() -> A + Send + 'static>(self, op: F) -> Self {
+        let program = self.program;
+        AsyncRustIO::new(async move {
+            match program.await {
+                Settled::Wrong(_) => Settled::Right(op()),
+                Settled::Empty => Settled::Value(op()),
+                other => other,
+            }
+        })
+    }
+
+    pub fn recover_with<F: FnOnce() -> Self + Send + 'static>(self, op: F) -> Self {
+        let program = self.program;
+        AsyncRustIO::new(async move {
+            match program.await {
+                Settled::Wrong(_) | Settled::Empty => op().program.await,
+                other => other,
+            }
+        })
+    }
+
+    /// Non-blocking delay using the tokio timer.
+    pub fn delay(self, time: Duration) -> Self {
+        let program = self.program;
+        AsyncRustIO::new(async move {
+            let settled = program.await;
+            if settled.is_ok() {
+                tokio::time::sleep(time).await;
+            }
+            settled
+        })
+    }
+
+    /// Operator to run every task in the Vector concurrently, each one spawned as a tokio task.
+    /// Once all of them are finished, we invoke the passed function with [Vector<A>] as input param.
+    /// If any of the tasks is [Wrong] the program is the first error, and otherwise if any of them
+    /// is [Empty], or the task panic, the program is [Empty]
+    pub fn parallel<Task: FnOnce() -> Self + Send + 'static, F: FnOnce(Vec<A>) -> Self + Send + 'static>(tasks: Vec<Task>, op: F) -> Self {
+        AsyncRustIO::new(async move {
+            let handles = tasks.into_iter()
+                .map(|task| tokio::spawn(async move { task().program.await }))
+                .collect::<Vec<_>>();
+            let mut values = vec!();
+            let mut any_empty = false;
+            for task_done in join_all(handles).await {
+                match task_done {
+                    Ok(Settled::Value(a) | Settled::Right(a)) => values.push(a),
+                    Ok(Settled::Wrong(e)) => return Settled::Wrong(e),
+                    _ => any_empty = true,
+                }
+            }
+            if any_empty {
+                return Settled::Empty;
+            }
+            op(values).program.await
+        })
+    }
+
+    /// Spawn the program, with the function applied, as a tokio task once the program runs.
+    /// The program continues with the [JoinHandle] of the task, so any combinator after it
+    /// waits for the forked task. If the task panics the program is [Empty].
+    pub fn fork<F: FnOnce(A) -> A + Send + 'static>(self, op: F) -> Self {
+        let program = self.map(op).program;
+        AsyncRustIO::new(async move {
+            tokio::spawn(program).await.unwrap_or(Settled::Empty)
+        })
+    }
+
+    /// Point where the forked task is awaited. The [JoinHandle] is already part of the program
+    /// after [fork], so it's kept for symmetry with [RustIO::join].
+    pub fn join(self) -> Self {
+        self
+    }
+
+    /// Consumer function that does not affect the current value of the monad.
+    pub fn daemon<F: FnOnce(&A) + Send + 'static>(self, op: F) -> Self {
+        self.peek(op)
+    }
+
+    pub fn peek<F: FnOnce(&A) + Send + 'static>(self, op: F) -> Self {
+        let program = self.program;
+        AsyncRustIO::new(async move {
+            let settled = program.await;
+            if let Settled::Value(v) | Settled::Right(v) = &settled {
+                op(v);
+            }
+            settled
+        })
+    }
+
+    pub fn on_error<F: FnOnce(&T) + Send + 'static>(self, op: F) -> Self {
+        let program = self.program;
+        AsyncRustIO::new(async move {
+            let settled = program.await;
+            if let Settled::Wrong(e) = &settled {
+                op(e);
+            }
+            settled
+        })
+    }
+
+    pub fn on_success<F: FnOnce(&A) + Send + 'static>(self, op: F) -> Self {
+        let program = self.program;
+        AsyncRustIO::new(async move {
+            let settled = program.await;
+            if let Settled::Right(v) = &settled {
+                op(v);
+            }
+            settled
+        })
+    }
+
+    /// Generic function to cover [at_some_point] [while] and [until]
+    fn at_some_point_cond<P: FnOnce() -> bool + Clone + Send + 'static, F: FnOnce(A) -> Self + Clone + Send + 'static>(self, cond: Option<bool>, predicate: P, op: F) -> Self where A: Clone {
+        let program = self.program;
+        AsyncRustIO::new(async move {
+            match program.await {
+                Settled::Value(a) | Settled::Right(a) => {
+                    loop {
+                        let result = op.clone()(a.clone()).program.await;
+                        if result.is_ok() || cond.is_some_and(|cond| predicate.clone()() == cond) {
+                            break result;
+                        }
+                    }
+                }
+                other => other,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn async_rio() {
+        let rio_program: AsyncRustIO<String, String> =
+            AsyncRustIO::of(String::from("hello"))
+                .flat_map(|v| AsyncRustIO::from_option(Some(v + " pure")))
+                .flat_map(|v| AsyncRustIO::from_future(async move { v + " functional" }))
+                .flat_map(|v| AsyncRustIO::from_result(Ok(v + " world")))
+                .map(|v| v + "!!");
+        let rio = rio_program.await;
+        assert_eq!(rio.get(), "hello pure functional world!!");
+    }
+
+    #[tokio::test]
+    async fn async_rio_map_other_type() {
+        let rio = AsyncRustIO::<String, String>::of(String::from("hello world!!"))
+            .map(|v| v.len())
+            .await;
+        assert_eq!(rio.get(), 13);
+    }
+
+    #[tokio::test]
+    async fn async_rio_error() {
+        let rio = AsyncRustIO::<String, i32>::from_future_result(async { Err(503) })
+            .map(|v| v.to_uppercase())
+            .map_error(|e| e.to_string())
+            .await;
+        assert_eq!(rio.failed(), "503");
+    }
+
+    #[tokio::test]
+    async fn async_rio_filter_recover() {
+        let rio = AsyncRustIO::<String, String>::of(String::from("hello"))
+            .filter(|v| v.len() > 10)
+            .recover(|| String::from("hello world!!"))
+            .await;
+        assert_eq!(rio.get(), "hello world!!");
+    }
+
+    #[tokio::test]
+    async fn async_rio_from_rust_io() {
+        let rust_io: RustIO<String, String> = RustIO::from_option(Some(String::from("hello")));
+        let rio = AsyncRustIO::from(rust_io)
+            .map(|v| v + " world!!")
+            .await;
+        assert_eq!(rio.get(), "hello world!!");
+    }
+
+    #[tokio::test]
+    async fn async_rio_delay() {
+        let start = Instant::now();
+        let rio = AsyncRustIO::<String, String>::of(String::from("hello world!!"))
+            .delay(Duration::from_millis(200))
+            .await;
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        assert_eq!(rio.get(), "hello world!!");
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn async_rio_delay_does_not_block_executor() {
+        let slow = AsyncRustIO::<String, String>::of(String::from("slow"))
+            .delay(Duration::from_millis(300));
+        let fast = AsyncRustIO::<String, String>::of(String::from("fast"))
+            .delay(Duration::from_millis(300));
+        let start = Instant::now();
+        let (slow, fast) = futures::join!(slow, fast);
+        assert!(start.elapsed() < Duration::from_millis(550));
+        assert_eq!(slow.get() + &fast.get(), "slowfast");
+    }
+
+    #[tokio::test]
+    async fn async_rio_zip() {
+        let rio = AsyncRustIO::zip(
+            || AsyncRustIO::<String, String>::of("hello".to_string()).delay(Duration::from_millis(100)),
+            || AsyncRustIO::of(1981).delay(Duration::from_millis(100)),
+            |a, b| AsyncRustIO::of(format!("{} {}", a, b)))
+            .await;
+        assert_eq!(rio.get(), "hello 1981");
+    }
+
+    #[tokio::test]
+    async fn async_rio_parallel() {
+        let parallel_tasks: Vec<fn() -> AsyncRustIO<String, String>> = vec![
+            || AsyncRustIO::from_option(Some("hello".to_string())).delay(Duration::from_millis(200)),
+            || AsyncRustIO::from_result(Ok(" world".to_string())).delay(Duration::from_millis(200)),
+            || AsyncRustIO::of("!!".to_string()).delay(Duration::from_millis(200)),
+        ];
+
+        let start = Instant::now();
+        let rio = AsyncRustIO::parallel(parallel_tasks, |tasks| AsyncRustIO::of(tasks.into_iter().collect()))
+            .await;
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert_eq!(rio.get(), "hello world!!");
+    }
+
+    #[tokio::test]
+    async fn async_rio_parallel_error() {
+        let parallel_tasks: Vec<fn() -> AsyncRustIO<String, String>> = vec![
+            || AsyncRustIO::of("hello".to_string()),
+            || AsyncRustIO::from_result(Err("burning world!!".to_string())),
+        ];
+
+        let rio = AsyncRustIO::parallel(parallel_tasks, |tasks| AsyncRustIO::of(tasks.into_iter().collect()))
+            .await;
+        assert_eq!(rio.failed(), "burning world!!");
+    }
+
+    #[tokio::test]
+    async fn async_rio_parallel_empty() {
+        let parallel_tasks: Vec<fn() -> AsyncRustIO<String, String>> = vec![
+            || AsyncRustIO::of("hello".to_string()),
+            || AsyncRustIO::from_option(None),
+        ];
+
+        let rio = AsyncRustIO::parallel(parallel_tasks, |tasks| AsyncRustIO::of(tasks.into_iter().collect()))
+            .await;
+        assert!(rio.is_empty());
+    }
+
+    #[tokio::test]
+    async fn async_rio_fork() {
+        let forked = Arc::new(Mutex::new(false));
+        let forked_copy = forked.clone();
+        let rio_program = AsyncRustIO::<String, String>::of(String::from("hello"))
+            .fork(move |v| {
+                *forked_copy.lock().unwrap() = true;
+                v.to_uppercase()
+            });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!*forked.lock().unwrap());
+        let rio = rio_program.join()
+            .map(|v| v + " world!!")
+            .await;
+        assert!(*forked.lock().unwrap());
+        assert_eq!(rio.get(), "HELLO world!!");
+    }
+
+    #[test]
+    fn async_rio_fork_outside_runtime() {
+        let rio_program = AsyncRustIO::<String, String>::of(String::from("hello"))
+            .fork(|v| v.to_uppercase());
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let rio = runtime.block_on(rio_program);
+        assert_eq!(rio.get(), "HELLO");
+    }
+
+    #[tokio::test]
+    async fn async_rio_eventually() {
+        let attempts = Arc::new(Mutex::new(0));
+        let rio = AsyncRustIO::<String, String>::of(String::from("hello"))
+            .at_some_point(move |v| {
+                let mut attempts = attempts.lock().unwrap();
+                *attempts += 1;
+                if *attempts < 3 {
+                    AsyncRustIO::from_result(Err("Error".to_string()))
+                } else {
+                    AsyncRustIO::from_result(Ok(v + " world"))
+                }
+            })
+            .await;
+        assert_eq!(rio.get(), "hello world");
+    }
+}
@@ -0,0 +1,287 @@
+use std::cell::RefCell;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{Lift, RustIO};
+use crate::RustIO::Empty;
+use crate::settled::Settled;
+
+///Handle of a task running in its own thread, created with [fork_fiber].
+/// [join] wait for the result, [timeout] wait for it up to a limit, and [cancel] discard it.
+/// A cancelled fiber, or one that timed out, is skipped if it did not start yet. A running one is
+/// interrupted at its next [flat_map], [delay], [at_some_point] or [retry] step, that ends as [Empty],
+/// and waits in [delay] and [retry] are woken up. Code that is not a [RustIO] step can check
+/// [is_interrupted] to stop.
+pub struct Fiber<A, T> {
+    receiver: Receiver<Settled<A, T>>,
+    interruption: Arc<Interruption>,
+}
+
+/// Cancel flag of a fiber, shared with the thread running it.
+#[derive(Default)]
+struct Interruption {
+    cancelled: Mutex<bool>,
+    wake: Condvar,
+}
+
+impl Interruption {
+    fn cancel(&self) {
+        *self.cancelled.lock().unwrap() = true;
+        self.wake.notify_all();
+    }
+
+    fn is_cancelled(&self) -> bool {
+        *self.cancelled.lock().unwrap()
+    }
+}
+
+thread_local! {
+    static INTERRUPTION: RefCell<Option<Arc<Interruption>>> = const { RefCell::new(None) };
+}
+
+fn current_interruption() -> Option<Arc<Interruption>> {
+    INTERRUPTION.with(|interruption| interruption.borrow().clone())
+}
+
+/// True in the thread of a [Fiber] that was cancelled.
+pub fn is_interrupted() -> bool {
+    current_interruption().is_some_and(|interruption| interruption.is_cancelled())
+}
+
+/// Sleep for [time], or until the fiber running in this thread is cancelled.
+/// Returns false if the sleep was interrupted.
+pub(crate) fn sleep(time: Duration) -> bool {
+    match current_interruption() {
+        None => {
+            thread::sleep(time);
+            true
+        }
+        Some(interruption) => {
+            let deadline = Instant::now() + time;
+            let mut cancelled = interruption.cancelled.lock().unwrap();
+            while !*cancelled {
+                let now = Instant::now();
+                if now >= deadline {
+                    return true;
+                }
+                cancelled = interruption.wake.wait_timeout(cancelled, deadline - now).unwrap().0;
+            }
+            false
+        }
+    }
+}
+
+impl<A: Send + 'static, T: Send + 'static> RustIO<A, T> {
+    /// Run the function in another thread, returning a [Fiber] handle of the task.
+    /// A [Fut] value is joined in the current thread before the fork.
+    pub fn fork_fiber<B: Send + 'static, F: FnOnce(A) -> RustIO<B, T> + Send + 'static>(self, op: F) -> Fiber<B, T> {
+        let settled = Settled::from(self);
+        let interruption = Arc::new(Interruption::default());
+        let fiber_interruption = interruption.clone();
+        let (sender, receiver) = mpsc::sync_channel(1);
+        thread::spawn(move || {
+            if fiber_interruption.is_cancelled() {
+                return;
+            }
+            INTERRUPTION.with(|interruption| *interruption.borrow_mut() = Some(fiber_interruption));
+            let result = match settled {
+                Settled::Value(a) | Settled::Right(a) => Settled::from(op(a)),
+                Settled::Wrong(e) => Settled::Wrong(e),
+                Settled::Empty => Settled::Empty,
+            };
+            let _ = sender.send(result);
+        });
+        Fiber { receiver, interruption }
+    }
+
+    /// Run the task in a [Fiber], waiting for it up to [time]. Once the time is over the task
+    /// is cancelled, and the program is [Empty].
+    pub fn timeout<F: FnOnce() -> Self + Send + 'static>(time: Duration, task: F) -> Self {
+        RustIO::<(), T>::of(()).fork_fiber(move |_| task()).timeout(time)
+    }
+
+    /// Run both tasks in their own [Fiber], and return the result of the first one to finish,
+    /// whatever the state is. The other fiber is cancelled, so it is interrupted as described
+    /// in [Fiber], and its result discarded.
+    /// If both tasks panic the program is [Empty].
+    pub fn race<Z1: FnOnce() -> Self + Send + 'static, Z2: FnOnce() -> Self + Send + 'static>(a: Z1, b: Z2) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let sender_copy = sender.clone();
+        let fiber_a = RustIO::<(), T>::of(()).fork_fiber(move |_| {
+            let _ = sender.send((true, Settled::from(a())));
+            RustIO::<(), T>::Empty()
+        });
+        let fiber_b = RustIO::<(), T>::of(()).fork_fiber(move |_| {
+            let _ = sender_copy.send((false, Settled::from(b())));
+            RustIO::<(), T>::Empty()
+        });
+        match receiver.recv() {
+            Ok((a_first, settled)) => {
+                if a_first { fiber_b.cancel() } else { fiber_a.cancel() }
+                RustIO::from(settled)
+            }
+            Err(_) => Empty(),
+        }
+    }
+}
+
+impl<A, T> Fiber<A, T> {
+    /// Block until the task finish. If the task panic the program is [Empty].
+    pub fn join(self) -> RustIO<A, T> {
+        match self.receiver.recv() {
+            Ok(settled) => RustIO::from(settled),
+            Err(_) => Empty(),
+        }
+    }
+
+    /// Block until the task finish, or up to [time]. Once the time is over the fiber
+    /// is cancelled, and the program is [Empty].
+    pub fn timeout(self, time: Duration) -> RustIO<A, T> {
+        match self.receiver.recv_timeout(time) {
+            Ok(settled) => RustIO::from(settled),
+            Err(_) => {
+                self.cancel();
+                Empty()
+            }
+        }
+    }
+
+    /// Cancel the task without waiting for it.
+    pub fn cancel(self) {
+        self.interruption.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    #[test]
+    fn rio_fiber_join() {
+        let fiber = RustIO::<String, String>::from_option(Some(String::from("hello")))
+            .fork_fiber(|v| {
+                println!("Fork. Variable:{} in Thread:{:?}", v, thread::current().id());
+                RustIO::of(v.len())
+            });
+        assert_eq!(fiber.join().get(), 5);
+    }
+
+    #[test]
+    fn rio_fiber_timeout() {
+        let start = Instant::now();
+        let rio_program = RustIO::<String, String>::of(String::from("hello"))
+            .fork_fiber(|v| {
+                thread::sleep(Duration::from_secs(2));
+                RustIO::of(v)
+            })
+            .timeout(Duration::from_millis(100));
+        assert!(rio_program.is_empty());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn rio_fiber_in_time() {
+        let rio_program = RustIO::<String, String>::of(String::from("hello"))
+            .fork_fiber(|v| RustIO::of(v + " world!!"))
+            .timeout(Duration::from_secs(2));
+        assert_eq!(rio_program.get(), "hello world!!");
+    }
+
+    #[test]
+    fn rio_fiber_cancel() {
+        let start = Instant::now();
+        RustIO::<String, String>::of(String::from("hello"))
+            .fork_fiber(|v| {
+                thread::sleep(Duration::from_secs(2));
+                RustIO::of(v)
+            })
+            .cancel();
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn rio_fiber_cancel_running() {
+        let started = Arc::new(Mutex::new(false));
+        let steps = Arc::new(Mutex::new(0));
+        let (stopped_sender, stopped) = mpsc::channel();
+        let fiber_started = started.clone();
+        let fiber_steps = steps.clone();
+        let fiber = RustIO::<String, String>::of(String::from("hello"))
+            .fork_fiber(move |v| {
+                *fiber_started.lock().unwrap() = true;
+                let rio_program = RustIO::of(v)
+                    .delay(Duration::from_secs(5))
+                    .flat_map(|v| {
+                        *fiber_steps.lock().unwrap() += 1;
+                        RustIO::of(v)
+                    });
+                let _ = stopped_sender.send(rio_program.is_empty());
+                rio_program
+            });
+        while !*started.lock().unwrap() {
+            thread::sleep(Duration::from_millis(5));
+        }
+        fiber.cancel();
+        assert_eq!(stopped.recv_timeout(Duration::from_secs(1)), Ok(true));
+        assert_eq!(*steps.lock().unwrap(), 0);
+    }
+
+    #[test]
+    fn rio_timeout() {
+        let start = Instant::now();
+        let rio_program: RustIO<String, String> = RustIO::timeout(Duration::from_millis(100),
+            || RustIO::of(String::from("slow")).delay(Duration::from_secs(2)));
+        assert!(rio_program.is_empty());
+        assert!(start.elapsed() < Duration::from_secs(1));
+        let rio_program: RustIO<String, String> = RustIO::timeout(Duration::from_secs(2),
+            || RustIO::of(String::from("fast")));
+        assert_eq!(rio_program.get(), "fast");
+    }
+
+    #[test]
+    fn rio_fiber_panic() {
+        let rio_program = RustIO::<String, String>::of(String::from("hello"))
+            .fork_fiber(|_| -> RustIO<String, String> { panic!("burning world!!") })
+            .join();
+        assert!(rio_program.is_empty());
+    }
+
+    #[test]
+    fn rio_race() {
+        let start = Instant::now();
+        let rio_program: RustIO<String, String> = RustIO::race(
+            || RustIO::of(String::from("slow")).delay(Duration::from_secs(2)),
+            || RustIO::of(String::from("fast")).delay(Duration::from_millis(50)));
+        assert_eq!(rio_program.get(), "fast");
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn rio_race_interrupts_loser() {
+        let (interrupted_sender, interrupted) = mpsc::channel();
+        let rio_program: RustIO<String, String> = RustIO::race(
+            move || {
+                let start = Instant::now();
+                while !is_interrupted() && start.elapsed() < Duration::from_secs(2) {
+                    thread::sleep(Duration::from_millis(5));
+                }
+                let _ = interrupted_sender.send(is_interrupted());
+                RustIO::of(String::from("slow"))
+            },
+            || RustIO::of(String::from("fast")).delay(Duration::from_millis(50)));
+        assert_eq!(rio_program.get(), "fast");
+        assert_eq!(interrupted.recv_timeout(Duration::from_secs(1)), Ok(true));
+    }
+
+    #[test]
+    fn rio_race_error() {
+        let rio_program: RustIO<String, String> = RustIO::race(
+            || RustIO::of(String::from("slow")).delay(Duration::from_secs(2)),
+            || RustIO::from_result(Err(String::from("burning world!!"))));
+        assert_eq!(rio_program.failed(), "burning world!!");
+    }
+}
@@ -0,0 +1,191 @@
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+
+#[cfg(feature = "tracing")]
+use crate::RustIO;
+#[cfg(feature = "tracing")]
+use crate::RustIO::{Empty, Fut, Right, Value, Wrong};
+#[cfg(feature = "tracing")]
+use crate::schedule::RetryReport;
+
+///Final state of a step, recorded in its span once the step is done.
+#[cfg(feature = "tracing")]
+pub(crate) trait Outcome {
+    fn outcome(&self) -> &'static str;
+
+    fn attempts(&self) -> Option<u32> {
+        None
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl<A, T> Outcome for RustIO<A, T> {
+    fn outcome(&self) -> &'static str {
+        match self {
+            Right(_) => "Right",
+            Wrong(_) => "Wrong",
+            Value(_) => "Value",
+            Empty() => "Empty",
+            Fut(_) => "Fut",
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl<A, T> Outcome for (RustIO<A, T>, RetryReport) {
+    fn outcome(&self) -> &'static str {
+        self.0.outcome()
+    }
+
+    fn attempts(&self) -> Option<u32> {
+        Some(self.1.attempts)
+    }
+}
+
+/// Run one combinator step inside a [rust_io] span, with the [step] name, the [outcome]
+/// of the step, its [duration_us], and the [attempts] of the retry operators.
+/// Steps invoked inside the function, like the ones of a [flat_map], are children of this span.
+#[cfg(feature = "tracing")]
+pub(crate) fn step<O: Outcome, F: FnOnce() -> O>(name: &'static str, op: F) -> O {
+    let span = tracing::info_span!("rust_io",
+        step = name,
+        outcome = tracing::field::Empty,
+        duration_us = tracing::field::Empty,
+        attempts = tracing::field::Empty);
+    let _enter = span.enter();
+    let start = Instant::now();
+    let output = op();
+    span.record("outcome", output.outcome());
+    span.record("duration_us", start.elapsed().as_micros() as u64);
+    if let Some(attempts) = output.attempts() {
+        span.record("attempts", attempts);
+    }
+    output
+}
+
+/// Without the [tracing] feature a step is just the function invocation.
+#[cfg(not(feature = "tracing"))]
+#[inline(always)]
+pub(crate) fn step<O, F: FnOnce() -> O>(_name: &'static str, op: F) -> O {
+    op()
+}
+
+/// Record in the current step the number of attempts of [at_some_point] operators.
+#[cfg(feature = "tracing")]
+pub(crate) fn record_attempts(attempts: u32) {
+    tracing::Span::current().record("attempts", attempts);
+}
+
+#[cfg(not(feature = "tracing"))]
+#[inline(always)]
+pub(crate) fn record_attempts(_attempts: u32) {}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use std::cell::Cell;
+    use std::collections::HashMap;
+    use std::fmt::Debug;
+    use std::rc::Rc;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::Subscriber;
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+    use tracing_subscriber::Layer;
+    use tracing_subscriber::registry::LookupSpan;
+
+    use crate::{Lift, rust_io, Schedule};
+
+    use super::*;
+
+    type Fields = HashMap<String, String>;
+
+    /// Layer that keeps the fields of every closed span.
+    #[derive(Clone, Default)]
+    struct Steps(Arc<Mutex<Vec<Fields>>>);
+
+    struct FieldsVisitor<'a>(&'a mut Fields);
+
+    impl Visit for FieldsVisitor<'_> {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name().to_string(), value.to_string());
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.0.insert(field.name().to_string(), format!("{:?}", value));
+        }
+    }
+
+    impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Steps {
+        fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+            let mut fields = Fields::new();
+            attrs.record(&mut FieldsVisitor(&mut fields));
+            ctx.span(id).unwrap().extensions_mut().insert(fields);
+        }
+
+        fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+            let span = ctx.span(id).unwrap();
+            let mut extensions = span.extensions_mut();
+            values.record(&mut FieldsVisitor(extensions.get_mut::<Fields>().unwrap()));
+        }
+
+        fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+            let fields = ctx.span(&id).unwrap().extensions_mut().remove::<Fields>().unwrap();
+            self.0.lock().unwrap().push(fields);
+        }
+    }
+
+    fn record_steps<A, F: FnOnce() -> A>(op: F) -> Vec<Fields> {
+        let steps = Steps::default();
+        let subscriber = tracing_subscriber::registry().with(steps.clone());
+        tracing::subscriber::with_default(subscriber, op);
+        let recorded = steps.0.lock().unwrap().clone();
+        recorded
+    }
+
+    fn find<'a>(steps: &'a [Fields], name: &str) -> &'a Fields {
+        steps.iter().find(|fields| fields["step"] == name).unwrap()
+    }
+
+    #[test]
+    fn rio_tracing_steps() {
+        let steps = record_steps(|| {
+            let rio_program: RustIO<String, String> = rust_io! {
+                 v <- RustIO::from_option(Some(String::from("hello")));
+                 t <- RustIO::<String, String>::from_result(Err(String::from("burning world!!")));
+                 yield v + &t;
+            };
+            assert_eq!(rio_program.failed(), "burning world!!");
+        });
+        assert_eq!(find(&steps, "from_option")["outcome"], "Value");
+        assert_eq!(find(&steps, "from_result")["outcome"], "Wrong");
+        assert_eq!(find(&steps, "flat_map")["outcome"], "Wrong");
+        assert!(find(&steps, "flat_map").contains_key("duration_us"));
+    }
+
+    #[test]
+    fn rio_tracing_empty() {
+        let steps = record_steps(|| {
+            RustIO::<String, String>::of(String::from("hello")).filter(|v| v.len() > 10)
+        });
+        assert_eq!(find(&steps, "filter")["outcome"], "Empty");
+    }
+
+    #[test]
+    fn rio_tracing_attempts() {
+        let steps = record_steps(|| {
+            let attempts = Rc::new(Cell::new(0));
+            RustIO::<i32, String>::of(1).at_some_point(move |v| {
+                attempts.set(attempts.get() + 1);
+                if attempts.get() < 3 { RustIO::from_option(None) } else { RustIO::of(v) }
+            });
+            RustIO::<i32, String>::of(1)
+                .retry(Schedule::fixed(Duration::from_millis(1)).with_max_attempts(2), |_| RustIO::from_option(None))
+        });
+        assert_eq!(find(&steps, "at_some_point")["attempts"], "3");
+        assert_eq!(find(&steps, "retry")["attempts"], "2");
+        assert_eq!(find(&steps, "retry")["outcome"], "Empty");
+    }
+}
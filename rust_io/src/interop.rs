@@ -0,0 +1,148 @@
+use std::iter::FromIterator;
+
+use futures::executor::block_on;
+
+use crate::RustIO;
+use crate::RustIO::{Empty, Fut, Right, Value, Wrong};
+
+/// [Ok] is [Right] and [Err] is [Wrong], like [from_result].
+impl<A, T> From<Result<A, T>> for RustIO<A, T> {
+    fn from(result: Result<A, T>) -> Self {
+        match result {
+            Ok(a) => Right(a),
+            Err(t) => Wrong(t),
+        }
+    }
+}
+
+/// [Some] is [Value] and [None] is [Empty], like [from_option].
+impl<A, T> From<Option<A>> for RustIO<A, T> {
+    fn from(option: Option<A>) -> Self {
+        match option {
+            Some(a) => Value(a),
+            None => Empty(),
+        }
+    }
+}
+
+/// [Empty] is [Ok(None)], so the program can continue with [?] in a function returning [Result].
+impl<A, T> From<RustIO<A, T>> for Result<Option<A>, T> {
+    fn from(rio: RustIO<A, T>) -> Self {
+        rio.into_result()
+    }
+}
+
+/// Sequence of programs into one program with all the values in order.
+/// The first [Wrong] or [Empty] stops the iteration, and it's the result of the program.
+impl<A, T> FromIterator<RustIO<A, T>> for RustIO<Vec<A>, T> {
+    fn from_iter<I: IntoIterator<Item=RustIO<A, T>>>(iter: I) -> Self {
+        let mut values = vec!();
+        for rio in iter {
+            match rio.into_result() {
+                Ok(Some(a)) => values.push(a),
+                Ok(None) => return Empty(),
+                Err(e) => return Wrong(e),
+            }
+        }
+        Right(values)
+    }
+}
+
+/// Iterator with the value of the program, or no values if it's not successful.
+impl<A, T> IntoIterator for RustIO<A, T> {
+    type Item = A;
+    type IntoIter = std::option::IntoIter<A>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.ok().into_iter()
+    }
+}
+
+/// Accessors that, unlike [get] and [failed], do not panic. A [Fut] is joined first.
+impl<A, T> RustIO<A, T> {
+    pub fn into_result(self) -> Result<Option<A>, T> {
+        match self {
+            Value(a) | Right(a) => Ok(Some(a)),
+            Wrong(e) => Err(e),
+            Empty() => Ok(None),
+            Fut(fut_box) => Ok(Some(block_on(fut_box))),
+        }
+    }
+
+    /// The value of the program, if it's successful.
+    pub fn ok(self) -> Option<A> {
+        self.into_result().ok().flatten()
+    }
+
+    /// The error of the program, if it's [Wrong].
+    pub fn err(self) -> Option<T> {
+        self.into_result().err()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+
+    use crate::{assert_empty, assert_right, assert_wrong, Lift};
+
+    use super::*;
+
+    fn find_user(id: u32) -> RustIO<String, String> {
+        match id {
+            1 => RustIO::from(Some(String::from("Politrons"))),
+            2 => RustIO::from(None),
+            _ => RustIO::from(Err(format!("user {} not allowed", id))),
+        }
+    }
+
+    fn greet(id: u32) -> Result<String, String> {
+        let user: Option<String> = Result::from(find_user(id))?;
+        Ok(format!("Hello {}", user.unwrap_or(String::from("stranger"))))
+    }
+
+    #[test]
+    fn rio_from() {
+        assert_right!(RustIO::<i32, String>::from(Ok(1981)), 1981);
+        assert_wrong!(RustIO::<i32, i32>::from(Err(503)), 503);
+        assert_right!(RustIO::<i32, String>::from(Some(1981)), 1981);
+        assert_empty!(RustIO::<i32, String>::from(None));
+    }
+
+    #[test]
+    fn rio_into_result() {
+        assert_eq!(greet(1), Ok(String::from("Hello Politrons")));
+        assert_eq!(greet(2), Ok(String::from("Hello stranger")));
+        assert_eq!(greet(3), Err(String::from("user 3 not allowed")));
+    }
+
+    #[test]
+    fn rio_from_iter() {
+        let rio_program: RustIO<Vec<String>, String> = vec![find_user(1), find_user(1)].into_iter().collect();
+        assert_right!(rio_program, vec!["Politrons", "Politrons"]);
+        let rio_program: RustIO<Vec<String>, String> = (1..5).map(find_user).collect();
+        assert_empty!(rio_program);
+        let rio_program: RustIO<Vec<String>, String> = vec![1, 3, 2].into_iter().map(find_user).collect();
+        assert_wrong!(rio_program, "user 3 not allowed");
+    }
+
+    #[test]
+    fn rio_into_iter() {
+        let users = (1..4).flat_map(find_user).collect::<Vec<_>>();
+        assert_eq!(users, vec!["Politrons"]);
+        for user in RustIO::<String, String>::of(String::from("hello")) {
+            assert_eq!(user, "hello");
+        }
+    }
+
+    #[test]
+    fn rio_accessors() {
+        assert_eq!(find_user(1).ok(), Some(String::from("Politrons")));
+        assert_eq!(find_user(2).ok(), None);
+        assert_eq!(find_user(3).ok(), None);
+        assert_eq!(find_user(3).err(), Some(String::from("user 3 not allowed")));
+        assert_eq!(find_user(1).err(), None);
+        let rio_program: RustIO<String, String> = Fut(async { String::from("hello") }.boxed_local());
+        assert_eq!(rio_program.into_result(), Ok(Some(String::from("hello"))));
+    }
+}
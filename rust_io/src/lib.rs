@@ -1,53 +1,236 @@
+use std::num::NonZeroUsize;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use futures::{FutureExt};
 use futures::executor::block_on;
-use futures::future::{join_all, LocalBoxFuture};
-use rand::{Rng, thread_rng};
+use futures::future::LocalBoxFuture;
 
 use crate::RustIO::{Empty, Fut, Right, Value, Wrong};
+use crate::instrument::step;
+
+#[cfg(feature = "async")]
+pub use crate::async_rust_io::AsyncRustIO;
+pub use crate::fiber::{Fiber, is_interrupted};
+pub use crate::parallel::TaskError;
+pub use crate::program::Program;
+pub use crate::resource::Resource;
+pub use crate::rio::RIO;
+pub use crate::schedule::{RetryReport, RetryStop, Schedule};
+pub use crate::stream::RustIOStream;
+pub use crate::validated::{NonEmptyVec, Validated};
+
+#[cfg(feature = "async")]
+mod async_rust_io;
+mod fiber;
+mod instrument;
+mod interop;
+mod parallel;
+mod program;
+mod resource;
+mod rio;
+mod schedule;
+#[cfg(feature = "serde")]
+mod serialization;
+mod settled;
+mod stream;
+pub mod testing;
+mod validated;
 
 /// Macro implementation for [rust_io] defining several operators to be used emulating
 /// Haskel [do notation]
 /// Work based on original idea of crate [do-notation]
+/// Besides binds, [let] and [yield], the block supports [if] guards, refutable pattern binds,
+/// and a trailing [recover] or [on_error] clause applied to the whole program.
 #[macro_export]
 macro_rules! rust_io {
+  // Trailing `recover` clause.
+  // Every statement is first moved into the brackets, so once the clause is found
+  // after the last `yield`, it can be applied to the whole program.
+  (@split [$($s:tt)*] yield $r:expr ; recover $h:block) => {
+    $crate::Lift::recover($crate::rust_io!(@do $($s)* yield $r ;), || $h)
+  };
+
+  // Trailing `on_error` clause, with or without the error binding.
+  (@split [$($s:tt)*] yield $r:expr ; on_error |$e:pat_param| $h:block) => {
+    $crate::Lift::on_error($crate::rust_io!(@do $($s)* yield $r ;), |$e| $h)
+  };
+
+  (@split [$($s:tt)*] yield $r:expr ; on_error $h:block) => {
+    $crate::Lift::on_error($crate::rust_io!(@do $($s)* yield $r ;), |_| $h)
+  };
+
+  (@split [$($s:tt)*] yield $r:expr ;) => {
+    $crate::rust_io!(@do $($s)* yield $r ;)
+  };
+
+  (@split [$($s:tt)*] let $p:pat = $e:expr ; $($r:tt)*) => {
+    $crate::rust_io!(@split [$($s)* let $p = $e ;] $($r)*)
+  };
+
+  (@split [$($s:tt)*] if $c:expr ; $($r:tt)*) => {
+    $crate::rust_io!(@split [$($s)* if $c ;] $($r)*)
+  };
+
+  (@split [$($s:tt)*] _ <- $x:expr ; $($r:tt)*) => {
+    $crate::rust_io!(@split [$($s)* _ <- $x ;] $($r)*)
+  };
+
+  (@split [$($s:tt)*] $bind:ident <- $x:expr ; $($r:tt)*) => {
+    $crate::rust_io!(@split [$($s)* $bind <- $x ;] $($r)*)
+  };
+
+  (@split [$($s:tt)*] $p:tt <- $x:expr ; $($r:tt)*) => {
+    $crate::rust_io!(@split [$($s)* $p <- $x ;] $($r)*)
+  };
+
+  (@split [$($s:tt)*] $($path:ident)::+ ($($p:tt)*) <- $x:expr ; $($r:tt)*) => {
+    $crate::rust_io!(@split [$($s)* $($path)::+ ($($p)*) <- $x ;] $($r)*)
+  };
+
+  (@split [$($s:tt)*] $($path:ident)::+ {$($p:tt)*} <- $x:expr ; $($r:tt)*) => {
+    $crate::rust_io!(@split [$($s)* $($path)::+ {$($p)*} <- $x ;] $($r)*)
+  };
+
+  (@split [$($s:tt)*] $a:expr) => {
+    $crate::rust_io!(@do $($s)* $a)
+  };
+
+  // Malformed blocks, each one with its own error.
+  (@split [$($s:tt)*] yield $r:expr ; $($t:tt)+) => {
+    compile_error!("rust_io! accepts a single `recover { .. }` or `on_error |e| { .. }` clause after `yield expr;`, as the last part of the block")
+  };
+
+  (@split [$($s:tt)*] $($t:tt)*) => {
+    $crate::rust_io!(@clause_without_yield [$($t)*] $($t)*)
+  };
+
+  (@clause_without_yield [$($all:tt)*] recover {$($h:tt)*} $($t:tt)*) => {
+    compile_error!("rust_io! `recover { .. }` clause must follow a `yield expr;` statement")
+  };
+
+  (@clause_without_yield [$($all:tt)*] on_error $($t:tt)*) => {
+    compile_error!("rust_io! `on_error |e| { .. }` clause must follow a `yield expr;` statement")
+  };
+
+  (@clause_without_yield [$($all:tt)*] $x:tt $($t:tt)*) => {
+    $crate::rust_io!(@clause_without_yield [$($all)*] $($t)*)
+  };
+
+  (@clause_without_yield [$($all:tt)*]) => {
+    $crate::rust_io!(@missing_semicolon $($all)*)
+  };
+
+  (@missing_semicolon <- $($t:tt)*) => {
+    $crate::rust_io!(@missing_semicolon_bind $($t)*)
+  };
+
+  (@missing_semicolon $x:tt $($t:tt)*) => {
+    $crate::rust_io!(@missing_semicolon $($t)*)
+  };
+
+  (@missing_semicolon) => {
+    compile_error!("rust_io! expects statements `x <- expr;`, `pattern <- expr;`, `let pattern = expr;` or `if condition;`, \
+                    ending with `yield expr;` and an optional `recover { .. }` or `on_error |e| { .. }` clause, or ending with an expression")
+  };
+
+  (@missing_semicolon_bind ; $($t:tt)*) => {
+    $crate::rust_io!(@missing_semicolon $($t)*)
+  };
+
+  (@missing_semicolon_bind yield $($t:tt)*) => {
+    compile_error!("rust_io! expects a `;` at the end of every `x <- expr` statement")
+  };
+
+  (@missing_semicolon_bind let $($t:tt)*) => {
+    compile_error!("rust_io! expects a `;` at the end of every `x <- expr` statement")
+  };
+
+  (@missing_semicolon_bind <- $($t:tt)*) => {
+    compile_error!("rust_io! expects a `;` at the end of every `x <- expr` statement")
+  };
+
+  (@missing_semicolon_bind $x:tt $($t:tt)*) => {
+    $crate::rust_io!(@missing_semicolon_bind $($t)*)
+  };
+
+  (@missing_semicolon_bind) => {
+    compile_error!("rust_io! expects a `;` at the end of every `x <- expr` statement")
+  };
+
   // Terminal `yield` arm.
   // It lifts the final plain value into the RustIO context, so the whole
   // do-notation block returns the same monadic type as the previous binds.
-  (yield $r:expr ;) => {
+  (@do yield $r:expr ;) => {
     $crate::Lift::lift($r)
   };
 
   // Plain Rust `let` binding inside the macro.
   // This does not call `flat_map`; it only creates a local variable and then
   // recursively expands the rest of the block.
-  (let $p:pat = $e:expr ; $($r:tt)*) => {{
+  (@do let $p:pat = $e:expr ; $($r:tt)*) => {{
     let $p = $e;
-    rust_io!($($r)*)
+    $crate::rust_io!(@do $($r)*)
   }};
 
+  // Guard, that behaves like `filter`.
+  // When the condition is false the program is `Empty`, and the rest of the block is not evaluated.
+  (@do if $c:expr ; $($r:tt)*) => {
+    if $c { $crate::rust_io!(@do $($r)*) } else { $crate::Lift::from_option(None) }
+  };
+
   // Monadic bind where the produced value is intentionally ignored.
   // The left expression must return a RustIO-like value. If it succeeds,
   // `flat_map` continues with the remaining macro tokens.
-  (_ <- $x:expr ; $($r:tt)*) => {
-    $x.flat_map(move |_| { rust_io!($($r)*) })
-  };
+  // The discarded program has the type of the rest of the block, so a constructor like
+  // `RustIO::from_result(Err(503))` still infers its value type.
+  (@do _ <- $x:expr ; $($r:tt)*) => {{
+    let discarded = $x;
+    let next = move || { $crate::rust_io!(@do $($r)*) };
+    $crate::same_type(&discarded, &next);
+    discarded.flat_map(move |_| next())
+  }};
 
   // Monadic bind that captures the successful value into `$bind`.
   // This is the do-notation equivalent of:
   // x.flat_map(|bind| next_step_using(bind))
-  ($bind:ident <- $x:expr ; $($r:tt)*) => {
-    $x.flat_map(move |$bind| { rust_io!($($r)*) })
+  (@do $bind:ident <- $x:expr ; $($r:tt)*) => {
+    $x.flat_map(move |$bind| { $crate::rust_io!(@do $($r)*) })
+  };
+
+  // Pattern binds, like `(a, b) <- expr;` or `Some(x) <- expr;`.
+  // If the value does not match the pattern the program is `Empty`.
+  (@do $p:tt <- $x:expr ; $($r:tt)*) => {
+    $crate::rust_io!(@bind ($p) $x ; $($r)*)
+  };
+
+  (@do $($path:ident)::+ ($($p:tt)*) <- $x:expr ; $($r:tt)*) => {
+    $crate::rust_io!(@bind ($($path)::+ ($($p)*)) $x ; $($r)*)
+  };
+
+  (@do $($path:ident)::+ {$($p:tt)*} <- $x:expr ; $($r:tt)*) => {
+    $crate::rust_io!(@bind ($($path)::+ {$($p)*}) $x ; $($r)*)
   };
 
   // Fallback terminal expression.
   // This allows the last line of the block to already be a RustIO value,
   // instead of a plain value that needs `yield`.
-  ($a:expr) => {
+  (@do $a:expr) => {
     $a
-  }
+  };
+
+  (@bind ($p:pat) $x:expr ; $($r:tt)*) => {
+    $x.flat_map(move |value| match value {
+      $p => { $crate::rust_io!(@do $($r)*) }
+      #[allow(unreachable_patterns)]
+      _ => $crate::Lift::from_option(None),
+    })
+  };
+
+  // Entry point of the block.
+  ($($t:tt)*) => {
+    $crate::rust_io!(@split [] $($t)*)
+  };
 }
 
 ///Specification to be implemented by a monad.
@@ -63,7 +246,7 @@ macro_rules! rust_io {
 /// Operators to filter and transform monad in one transaction
 /// [when][when_rio]
 /// Operators to recover from side-effects
-/// [recover][recover_with][eventually]
+/// [recover][recover_with][eventually][retry]
 /// To slow the monad execution
 /// [delay]
 /// To unwrap the value from monad.
@@ -72,7 +255,12 @@ macro_rules! rust_io {
 /// [is_ok][is_failed][is_empty]
 /// Async task executions
 /// [parallel][fork]
+/// Operators [map][map_error][flat_map][zip] can change the value or error type,
+/// returning the same monad shape described by [Lifted].
 pub trait Lift<A, T> {
+    /// The same monad holding a value of type [B] and an error of type [U].
+    type Lifted<B, U>: Lift<B, U>;
+
     fn lift(a: A) -> Self;
 
     fn of(a: A) -> Self;
@@ -101,23 +289,29 @@ pub trait Lift<A, T> {
 
     fn is_empty(&self) -> bool;
 
-    fn map<F: FnOnce(A) -> A>(self, op: F) -> Self;
+    fn map<B, F: FnOnce(A) -> B>(self, op: F) -> Self::Lifted<B, T>;
 
-    fn map_error<F: FnOnce(T) -> T>(self, op: F) -> Self;
+    fn map_error<U, F: FnOnce(T) -> U>(self, op: F) -> Self::Lifted<A, U>;
 
-    fn flat_map<F: FnOnce(A) -> Self>(self, op: F) -> Self;
+    fn flat_map<B, F: FnOnce(A) -> Self::Lifted<B, T>>(self, op: F) -> Self::Lifted<B, T>;
 
-    fn at_some_point<F: FnOnce(A) -> Self>(self, op: F) -> Self where A: Clone, F: Clone;
+    fn at_some_point<F: FnOnce(A) -> Self + Clone>(self, op: F) -> Self where A: Clone;
 
-    fn at_some_point_while<P: FnOnce() -> bool, F: FnOnce(A) -> Self>(self, predicate: P, op: F) -> Self where A: Clone, P: Clone, F: Clone;
+    fn at_some_point_while<P: FnOnce() -> bool + Clone, F: FnOnce(A) -> Self + Clone>(self, predicate: P, op: F) -> Self where A: Clone;
 
-    fn at_some_point_until<P: FnOnce() -> bool, F: FnOnce(A) -> Self>(self, predicate: P, op: F) -> Self where A: Clone, P: Clone, F: Clone;
+    fn at_some_point_until<P: FnOnce() -> bool + Clone, F: FnOnce(A) -> Self + Clone>(self, predicate: P, op: F) -> Self where A: Clone;
+
+    /// Run [op] with the value until it succeeds, waiting between attempts as the [schedule] says.
+    /// A program without value is not retried.
+    fn retry<F: FnOnce(A) -> Self + Clone>(self, schedule: Schedule, op: F) -> (Self, RetryReport) where A: Clone, Self: Sized {
+        retry_with_schedule(self, schedule, op)
+    }
 
     fn when<P: FnOnce(&A) -> bool, F: FnOnce(A) -> A>(self, predicate: P, op: F) -> Self;
 
     fn when_rio<P: FnOnce(&A) -> bool, F: FnOnce(A) -> Self>(self, predicate: P, op: F) -> Self;
 
-    fn zip<Z1: FnOnce() -> Self, Z2: FnOnce() -> Self, F: FnOnce(A, A) -> Self>(a: Z1, b: Z2, op: F) -> Self;
+    fn zip<B, C, Z1: FnOnce() -> Self, Z2: FnOnce() -> Self::Lifted<B, T>, F: FnOnce(A, B) -> Self::Lifted<C, T>>(a: Z1, b: Z2, op: F) -> Self::Lifted<C, T>;
 
     fn filter<F: FnOnce(&A) -> bool>(self, op: F) -> Self;
 
@@ -129,21 +323,21 @@ pub trait Lift<A, T> {
 
     fn delay(self, time: Duration) -> Self;
 
-    fn parallel<Task: FnOnce() -> Self, F: FnOnce(Vec<A>) -> Self>(tasks: Vec<Task>, op: F) -> Self;
+    fn parallel<Task: FnOnce() -> Self + Send + 'static, F: FnOnce(Vec<A>) -> Self>(tasks: Vec<Task>, op: F) -> Self::Lifted<A, TaskError<T>> where A: Send + 'static, T: Send + 'static;
 
     /// Provide [A:'static] in the definition it can extend the lifetime of a specific type
-    fn fork<F: FnOnce(A) -> A>(self, op: F) -> Self where A: 'static, F: 'static;
+    fn fork<F: FnOnce(A) -> A + 'static>(self, op: F) -> Self where A: 'static;
 
     /// Provide [A:'static] in the definition it can extend the lifetime of a specific type
     fn join(self) -> Self;
 
-    fn daemon<F: FnOnce(&A) -> ()>(self, op: F) -> Self;
+    fn daemon<F: FnOnce(&A)>(self, op: F) -> Self;
 
-    fn peek<F: FnOnce(&A) -> ()>(self, op: F) -> Self;
+    fn peek<F: FnOnce(&A)>(self, op: F) -> Self;
 
-    fn on_error<F: FnOnce(&T) -> ()>(self, op: F) -> Self;
+    fn on_error<F: FnOnce(&T)>(self, op: F) -> Self;
 
-    fn on_success<F: FnOnce(&A) -> ()>(self, op: F) -> Self;
+    fn on_success<F: FnOnce(&A)>(self, op: F) -> Self;
 }
 
 ///Data structure to be used as the monad to be implemented as [Lift]
@@ -158,43 +352,59 @@ pub enum RustIO<A, T> {
 
 /// Implementation of the Monad Lift.
 impl<A, T> Lift<A, T> for RustIO<A, T> {
+    type Lifted<B, U> = RustIO<B, U>;
+
     fn lift(a: A) -> Self {
         RustIO::of(a)
     }
 
     /// Pure value to create RustIO monad without side-effects.
     fn of(a: A) -> Self {
-        Value(a)
+        step("of", move || {
+            Value(a)
+        })
     }
 
     fn from_func(f: fn() -> A) -> Self {
-        Value(f())
+        step("from_func", move || {
+            Value(f())
+        })
     }
 
     fn from_option_func(f: fn() -> Option<A>) -> Self {
-        RustIO::from_option(f())
+        step("from_option_func", move || {
+            RustIO::from_option(f())
+        })
     }
 
     fn from_result_func(f: fn() -> Result<A, T>) -> Self {
-        RustIO::from_result(f())
+        step("from_result_func", move || {
+            RustIO::from_result(f())
+        })
     }
 
     fn from_option(a: Option<A>) -> Self {
-        match a {
-            None => Empty(),
-            Some(v) => Value(v)
-        }
+        step("from_option", move || {
+            match a {
+                None => Empty(),
+                Some(v) => Value(v)
+            }
+        })
     }
 
     fn from_result(a: Result<A, T>) -> Self {
-        match a {
-            Ok(v) => Right(v),
-            Err(t) => Wrong(t)
-        }
+        step("from_result", move || {
+            match a {
+                Ok(v) => Right(v),
+                Err(t) => Wrong(t)
+            }
+        })
     }
 
     fn merge<F: FnOnce(A, A) -> Self>(a: Self, b: Self, op: F) -> Self {
-        return a.flat_map(|x| b.flat_map(|y| op(x, y)));
+        step("merge", move || {
+            a.flat_map(|x| b.flat_map(|y| op(x, y)))
+        })
     }
 
     fn get(self) -> A {
@@ -220,6 +430,7 @@ impl<A, T> Lift<A, T> for RustIO<A, T> {
         }
     }
 
+    #[allow(clippy::match_like_matches_macro)]
     fn is_ok(&self) -> bool {
         match self {
             Value(_) => true,
@@ -228,6 +439,7 @@ impl<A, T> Lift<A, T> for RustIO<A, T> {
         }
     }
 
+    #[allow(clippy::match_like_matches_macro)]
     fn is_failed(&self) -> bool {
         match self {
             Value(_) => false,
@@ -236,6 +448,7 @@ impl<A, T> Lift<A, T> for RustIO<A, T> {
         }
     }
 
+    #[allow(clippy::match_like_matches_macro)]
     fn is_empty(&self) -> bool {
         match self {
             Value(_) => false,
@@ -244,251 +457,323 @@ impl<A, T> Lift<A, T> for RustIO<A, T> {
         }
     }
 
-    fn map<F: FnOnce(A) -> A>(self, op: F) -> Self {
-        match self {
-            Value(v) => Value(op(v)),
-            Right(v) => Right(op(v)),
-            _ => self,
-        }
+    /// A pending [Fut] is joined before the function is applied, since the value type changes.
+    fn map<B, F: FnOnce(A) -> B>(self, op: F) -> RustIO<B, T> {
+        step("map", move || {
+            match self {
+                Value(v) => Value(op(v)),
+                Right(v) => Right(op(v)),
+                Wrong(e) => Wrong(e),
+                Empty() => Empty(),
+                Fut(fut_box) => Value(op(block_on(fut_box))),
+            }
+        })
     }
 
-    fn map_error<F: FnOnce(T) -> T>(self, op: F) -> Self {
-        match self {
-            Wrong(e) => Wrong(op(e)),
-            _ => self
-        }
+    fn map_error<U, F: FnOnce(T) -> U>(self, op: F) -> RustIO<A, U> {
+        step("map_error", move || {
+            match self {
+                Wrong(e) => Wrong(op(e)),
+                Value(v) => Value(v),
+                Right(v) => Right(v),
+                Empty() => Empty(),
+                Fut(fut_box) => Fut(fut_box),
+            }
+        })
     }
 
-    fn flat_map<F: FnOnce(A) -> Self>(self, op: F) -> Self {
-        match self {
-            Value(a) | Right(a) => op(a),
-            Empty() => Empty(),
-            Wrong(e) => Wrong(e),
-            _ => self
-        }
+    fn flat_map<B, F: FnOnce(A) -> RustIO<B, T>>(self, op: F) -> RustIO<B, T> {
+        step("flat_map", move || {
+            match self {
+                Value(_) | Right(_) if fiber::is_interrupted() => Empty(),
+                Value(a) | Right(a) => op(a),
+                Empty() => Empty(),
+                Wrong(e) => Wrong(e),
+                Fut(fut_box) => op(block_on(fut_box)),
+            }
+        })
     }
 
     ///Returns an effect that ignores errors and runs repeatedly until it [at_some_point] succeeds
     /// We mark A type as Clone since we need a clone of the value for each iteration in the loop.
     /// In case you need a backoff between iterations, or a escape clause, you can use
     /// [until] or [while] [at_some_point] operator conditions.
-    fn at_some_point<F: FnOnce(A) -> Self>(self, op: F) -> Self where A: Clone, F: Clone {
-        match self {
-            Value(a) | Right(a) => {
-                loop {
-                    let op_copy = op.clone();
-                    let a_copy = a.clone();
-                    let result = op_copy(a_copy);
-                    if result.is_ok() {
-                        break result;
+    fn at_some_point<F: FnOnce(A) -> Self + Clone>(self, op: F) -> Self where A: Clone {
+        step("at_some_point", move || {
+            match self {
+                Value(a) | Right(a) => {
+                    let mut attempts = 0;
+                    loop {
+                        let op_copy = op.clone();
+                        let a_copy = a.clone();
+                        let result = op_copy(a_copy);
+                        attempts += 1;
+                        if result.is_ok() {
+                            instrument::record_attempts(attempts);
+                            break result;
+                        }
                     }
                 }
+                _ => self
             }
-            _ => self
-        }
+        })
     }
 
     /// Retry pattern of a task while a predicate condition is [false]
-    fn at_some_point_while<P: FnOnce() -> bool, F: FnOnce(A) -> Self>(self, predicate: P, op: F) -> Self where A: Clone, P: Clone, F: Clone {
-        self.at_some_point_cond(false, predicate, op)
+    fn at_some_point_while<P: FnOnce() -> bool + Clone, F: FnOnce(A) -> Self + Clone>(self, predicate: P, op: F) -> Self where A: Clone {
+        step("at_some_point_while", move || {
+            self.at_some_point_cond(false, predicate, op)
+        })
     }
 
     /// Retry pattern of a task while a predicate condition is [true]
-    fn at_some_point_until<P: FnOnce() -> bool, F: FnOnce(A) -> Self>(self, predicate: P, op: F) -> Self where A: Clone, P: Clone, F: Clone {
-        self.at_some_point_cond(true, predicate, op)
+    fn at_some_point_until<P: FnOnce() -> bool + Clone, F: FnOnce(A) -> Self + Clone>(self, predicate: P, op: F) -> Self where A: Clone {
+        step("at_some_point_until", move || {
+            self.at_some_point_cond(true, predicate, op)
+        })
+    }
+
+    /// Retry pattern of a task following the [Schedule] policy, waiting between attempts.
+    /// Together with the last result, it returns a [RetryReport] with the number of attempts
+    /// and the reason why it stopped, so it's available also when the program give up.
+    fn retry<F: FnOnce(A) -> Self + Clone>(self, schedule: Schedule, op: F) -> (Self, RetryReport) where A: Clone {
+        step("retry", move || retry_with_schedule(self, schedule, op))
     }
 
     fn when<P: FnOnce(&A) -> bool, F: FnOnce(A) -> A>(self, predicate: P, op: F) -> Self {
-        return match self {
-            Value(t) => {
-                let x = t;
-                return if predicate(&x) { Value(op(x)) } else { Empty() };
-            }
-            Empty() => Empty(),
-            Right(a) => {
-                let x = a;
-                return if predicate(&x) { Right(op(x)) } else { Empty() };
+        step("when", move || {
+            match self {
+                Value(t) => {
+                    let x = t;
+                    if predicate(&x) { Value(op(x)) } else { Empty() }
+                }
+                Empty() => Empty(),
+                Right(a) => {
+                    let x = a;
+                    if predicate(&x) { Right(op(x)) } else { Empty() }
+                }
+                Wrong(e) => Wrong(e),
+                _ => self
             }
-            Wrong(e) => Wrong(e),
-            _ => self
-        };
+        })
     }
 
     fn when_rio<P: FnOnce(&A) -> bool, F: FnOnce(A) -> Self>(self, predicate: P, op: F) -> Self {
-        return match self {
-            Value(t) => {
-                let x = t;
-                return if predicate(&x) { op(x) } else { Empty() };
-            }
-            Empty() => Empty(),
-            Right(a) => {
-                let x = a;
-                return if predicate(&x) { op(x) } else { Empty() };
+        step("when_rio", move || {
+            match self {
+                Value(t) => {
+                    let x = t;
+                    if predicate(&x) { op(x) } else { Empty() }
+                }
+                Empty() => Empty(),
+                Right(a) => {
+                    let x = a;
+                    if predicate(&x) { op(x) } else { Empty() }
+                }
+                Wrong(e) => Wrong(e),
+                _ => self
             }
-            Wrong(e) => Wrong(e),
-            _ => self
-        };
+        })
     }
 
-    fn zip<Z1: FnOnce() -> Self, Z2: FnOnce() -> Self, F: FnOnce(A, A) -> Self>(a: Z1, b: Z2, op: F) -> Self {
-        let empty = Empty();
-        let (zip_1, zip_2) = block_on(empty.run_future_zip_tasks(a, b));
-        if (zip_1.is_ok() || !zip_1.is_empty()) && (zip_2.is_ok() || !zip_2.is_empty()) {
-            return op(zip_1.get(), zip_2.get());
-        }
-        return empty;
+    fn zip<B, C, Z1: FnOnce() -> Self, Z2: FnOnce() -> RustIO<B, T>, F: FnOnce(A, B) -> RustIO<C, T>>(a: Z1, b: Z2, op: F) -> RustIO<C, T> {
+        step("zip", move || {
+            let (zip_1, zip_2) = block_on(run_future_zip_tasks(a, b));
+            if zip_1.is_ok() && zip_2.is_ok() {
+                return op(zip_1.get(), zip_2.get());
+            }
+            Empty()
+        })
     }
 
     fn filter<F: FnOnce(&A) -> bool>(self, op: F) -> Self {
-        return match self {
-            Value(t) => {
-                let x = t;
-                return if op(&x) { Value(x) } else { Empty() };
-            }
-            Empty() => Empty(),
-            Right(a) => {
-                let x = a;
-                return if op(&x) { Right(x) } else { Empty() };
+        step("filter", move || {
+            match self {
+                Value(t) => {
+                    let x = t;
+                    if op(&x) { Value(x) } else { Empty() }
+                }
+                Empty() => Empty(),
+                Right(a) => {
+                    let x = a;
+                    if op(&x) { Right(x) } else { Empty() }
+                }
+                Wrong(e) => Wrong(e),
+                _ => self
             }
-            Wrong(e) => Wrong(e),
-            _ => self
-        };
+        })
     }
 
     fn fold<F: FnOnce(A) -> A>(self, default: A, op: F) -> Self {
-        match self {
-            Value(v) => Value(op(v)),
-            Right(v) => Right(op(v)),
-            Empty() => Value(default),
-            _ => self
-        }
+        step("fold", move || {
+            self.fold_into(default, op)
+        })
     }
 
     fn recover<F: FnOnce() -> A>(self, op: F) -> Self {
-        match self {
-            Wrong(_) => Right(op()),
-            Empty() => Value(op()),
-            _ => self
-        }
+        step("recover", move || {
+            match self {
+                Wrong(_) => Right(op()),
+                Empty() => Value(op()),
+                _ => self
+            }
+        })
     }
 
     fn recover_with<F: FnOnce() -> Self>(self, op: F) -> Self {
-        match self {
-            Wrong(_) | Empty() => op(),
-            _ => self
-        }
+        step("recover_with", move || {
+            match self {
+                Wrong(_) | Empty() => op(),
+                _ => self
+            }
+        })
     }
 
     fn delay(self, time: Duration) -> Self {
-        match self {
-            Value(_) | Right(_) => {
-                thread::sleep(time);
-                self
+        step("delay", move || {
+            match self {
+                Value(_) | Right(_) => {
+                    if fiber::sleep(time) { self } else { Empty() }
+                }
+                _ => self
             }
-            _ => self
-        }
+        })
     }
 
-    /// Operator to run every task in the Vector asynchronously using async.
-    /// After we create the list of Futures, we use [join_all] to run all futures in parallel.
-    /// Once all of them are finished, we invoke the passed function with [Vector<A>] as input param
-    fn parallel<Task: FnOnce() -> Self, F: FnOnce(Vec<A>) -> Self>(tasks: Vec<Task>, op: F) -> Self {
-        let empty = Empty();
-        let tasks_done = block_on(empty.run_future_tasks(tasks));
-        let find_error_tasks = &tasks_done;
-        return match find_error_tasks.into_iter().find(|rio| rio.is_empty() || !rio.is_ok()) {
-            Some(_) => {
-                println!("Some of the task failed. Returning Empty value");
-                empty
-            }
-            None => {
-                let rios = tasks_done.into_iter()
-                    .fold(vec!(), |rios, task_done| {
-                        return rios.into_iter().chain(vec![task_done.get()]).collect::<Vec<_>>();
-                    });
-                op(rios)
-            }
-        };
+    /// Operator to run every task in the Vector in threads, as many at a time as the machine
+    /// has available parallelism, as [parallel_bounded] does.
+    /// Once all of them are finished, we invoke the passed function with [Vector<A>] as input param.
+    /// The first task that is [Wrong] or panics stops starting new tasks, and the program is that
+    /// error as [TaskError]. If no task fails, but some of them are [Empty], the program is [Empty].
+    fn parallel<Task: FnOnce() -> Self + Send + 'static, F: FnOnce(Vec<A>) -> Self>(tasks: Vec<Task>, op: F) -> RustIO<A, TaskError<T>> where A: Send + 'static, T: Send + 'static {
+        step("parallel", move || {
+            let max_parallelism = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+            RustIO::parallel_bounded(max_parallelism, tasks)
+                .flat_map(|values| op(values).map_error(TaskError::Failed))
+        })
     }
 
     /// It run the execution of the task in another green thread
     /// We use type [Fut] to wrap the [LocalBoxFuture<A>] which it contains the output of the function execution.
-    fn fork<F: FnOnce(A) -> A>(self, op: F) -> Self where A: 'static, F: 'static {
-        match self {
-            Value(v) | Right(v) => {
-                Fut(async { op(v) }.boxed_local())
+    fn fork<F: FnOnce(A) -> A + 'static>(self, op: F) -> Self where A: 'static {
+        step("fork", move || {
+            match self {
+                Value(v) | Right(v) => {
+                    Fut(async { op(v) }.boxed_local())
+                }
+                _ => self,
             }
-            _ => self,
-        }
+        })
     }
 
     ///Join the [LocalBoxFuture<A>].
     fn join(self) -> Self {
-        block_on(self.unbox_fork())
+        step("join", move || {
+            block_on(self.unbox_fork())
+        })
     }
 
     /// async consumer function that does not affect the current value of the monad.
-    fn daemon<F: FnOnce(&A) -> ()>(self, op: F) -> Self {
-        return block_on(self.run_daemon(op));
-    }
-
-    fn peek<F: FnOnce(&A) -> ()>(self, op: F) -> Self {
-        return match self {
-            Value(v) => {
-                let x = v;
-                op(&x);
-                Value(x)
-            }
-            Right(v) => {
-                let x = v;
-                op(&x);
-                Right(x)
+    fn daemon<F: FnOnce(&A)>(self, op: F) -> Self {
+        step("daemon", move || {
+            block_on(self.run_daemon(op))
+        })
+    }
+
+    fn peek<F: FnOnce(&A)>(self, op: F) -> Self {
+        step("peek", move || {
+            match self {
+                Value(v) => {
+                    let x = v;
+                    op(&x);
+                    Value(x)
+                }
+                Right(v) => {
+                    let x = v;
+                    op(&x);
+                    Right(x)
+                }
+                _ => self
             }
-            _ => self
-        };
+        })
     }
 
-    fn on_error<F: FnOnce(&T) -> ()>(self, op: F) -> Self {
-        return match self {
-            Wrong(v) => {
-                let x = v;
-                op(&x);
-                Wrong(x)
+    fn on_error<F: FnOnce(&T)>(self, op: F) -> Self {
+        step("on_error", move || {
+            match self {
+                Wrong(v) => {
+                    let x = v;
+                    op(&x);
+                    Wrong(x)
+                }
+                _ => self
             }
-            _ => self
-        };
+        })
     }
 
-    fn on_success<F: FnOnce(&A) -> ()>(self, op: F) -> Self {
-        return match self {
-            Right(v) => {
-                let x = v;
-                op(&x);
-                Right(x)
+    fn on_success<F: FnOnce(&A)>(self, op: F) -> Self {
+        step("on_success", move || {
+            match self {
+                Right(v) => {
+                    let x = v;
+                    op(&x);
+                    Right(x)
+                }
+                _ => self
             }
-            _ => self
-        };
+        })
     }
 }
 
-impl<A, T> RustIO<A, T> {
-    async fn run_future_zip_tasks<Z1: FnOnce() -> Self, Z2: FnOnce() -> Self>(&self, a: Z1, b: Z2) -> (RustIO<A, T>, RustIO<A, T>) {
-        let future_zip1 = async {
-            a()
-        };
-        let future_zip2 = async {
-            b()
-        };
-        return futures::join!(future_zip1,future_zip2);
+/// [retry] loop shared by every [Lift], returning the last result of [op].
+fn retry_with_schedule<A: Clone, T, M: Lift<A, T>, F: FnOnce(A) -> M + Clone>(program: M, schedule: Schedule, op: F) -> (M, RetryReport) {
+    if !program.is_ok() {
+        return (program, RetryReport { attempts: 0, stop: RetryStop::Skipped });
+    }
+    let a = program.get();
+    let start = Instant::now();
+    let mut attempts = 0;
+    loop {
+        let op_copy = op.clone();
+        let result = op_copy(a.clone());
+        attempts += 1;
+        if result.is_ok() {
+            break (result, RetryReport { attempts, stop: RetryStop::Succeeded });
+        }
+        match schedule.next(attempts, start.elapsed()) {
+            Ok(delay) if fiber::sleep(delay) => {}
+            Ok(_) => break (result, RetryReport { attempts, stop: RetryStop::Interrupted }),
+            Err(stop) => break (result, RetryReport { attempts, stop }),
+        }
     }
+}
+
+/// Used by [rust_io] to give a discarded program the type of the rest of the block.
+#[doc(hidden)]
+pub fn same_type<M, F: FnOnce() -> M>(_discarded: &M, _next: &F) {}
+
+/// Run both [zip] branches as futures. Each branch can hold a different value type.
+async fn run_future_zip_tasks<A, B, T, Z1: FnOnce() -> RustIO<A, T>, Z2: FnOnce() -> RustIO<B, T>>(a: Z1, b: Z2) -> (RustIO<A, T>, RustIO<B, T>) {
+    let future_zip1 = async {
+        a()
+    };
+    let future_zip2 = async {
+        b()
+    };
+    futures::join!(future_zip1,future_zip2)
+}
 
-    async fn run_future_tasks<Task: FnOnce() -> Self>(&self, tasks: Vec<Task>) -> Vec<RustIO<A, T>> {
-        let future_tasks = tasks.into_iter()
-            .fold(vec!(), |futures, task: Task| {
-                let future_task = vec![async { return task(); }];
-                return futures.into_iter().chain(future_task).collect::<Vec<_>>();
-            });
-        return join_all(future_tasks).await;
+impl<A, T> RustIO<A, T> {
+    /// [fold] into a value of another type. The [default] is the value of an [Empty] program,
+    /// so it has the type of the output, and the input type is inferred from the program.
+    pub fn fold_into<B, F: FnOnce(A) -> B>(self, default: B, op: F) -> RustIO<B, T> {
+        match self {
+            Value(v) => Value(op(v)),
+            Right(v) => Right(op(v)),
+            Empty() => Value(default),
+            Wrong(e) => Wrong(e),
+            Fut(fut_box) => Value(op(block_on(fut_box))),
+        }
     }
 
     async fn unbox_fork(self) -> RustIO<A, T> {
@@ -501,8 +786,8 @@ impl<A, T> RustIO<A, T> {
         }
     }
 
-    async fn run_daemon<F: FnOnce(&A) -> ()>(self, op: F) -> RustIO<A, T> {
-        return match self {
+    async fn run_daemon<F: FnOnce(&A)>(self, op: F) -> RustIO<A, T> {
+        match self {
             Value(v) => {
                 let x = v;
                 async { op(&x) }.await;
@@ -514,21 +799,28 @@ impl<A, T> RustIO<A, T> {
                 Right(x)
             }
             _ => self
-        };
+        }
     }
 
     /// Generic function to cover [at_some_point] [while] and [until]
-    fn at_some_point_cond<P: FnOnce() -> bool, F: FnOnce(A) -> Self>(self, cond: bool, predicate: P, op: F) -> Self where A: Clone, P: Clone, F: Clone {
+    fn at_some_point_cond<P: FnOnce() -> bool + Clone, F: FnOnce(A) -> Self + Clone>(self, cond: bool, predicate: P, op: F) -> Self where A: Clone {
         match self {
             Value(a) | Right(a) => {
+                let mut attempts = 0;
                 loop {
                     let op_copy = op.clone();
                     let predicate_copy = predicate.clone();
                     let a_copy = a.clone();
                     let result = op_copy(a_copy);
+                    attempts += 1;
                     if result.is_ok() || predicate_copy() == cond {
+                        instrument::record_attempts(attempts);
                         break result;
                     }
+                    if fiber::is_interrupted() {
+                        instrument::record_attempts(attempts);
+                        break Empty();
+                    }
                 }
             }
             _ => self
@@ -537,7 +829,12 @@ impl<A, T> RustIO<A, T> {
 }
 
 #[cfg(test)]
+#[allow(unused_variables, clippy::needless_return, clippy::bool_assert_comparison, clippy::unnecessary_to_owned, clippy::redundant_closure, clippy::vec_init_then_push)]
 mod tests {
+    use std::thread;
+
+    use rand::{Rng, thread_rng};
+
     use super::*;
 
     #[test]
@@ -555,7 +852,7 @@ mod tests {
         };
         println!("${:?}", rio_program.is_empty());
         println!("${:?}", rio_program.is_ok());
-        assert_eq!(rio_program.get(), "hello pure functional world!!!!");
+        assert_right!(rio_program, "hello pure functional world!!!!");
     }
 
     #[test]
@@ -570,7 +867,7 @@ mod tests {
         };
         println!("${:?}", rio_program.is_empty());
         println!("${:?}", rio_program.is_ok());
-        assert_eq!(rio_program.get(), "HELLO WORLD!!");
+        assert_right!(rio_program, "HELLO WORLD!!");
     }
 
     #[test]
@@ -584,7 +881,7 @@ mod tests {
         };
         println!("${:?}", rio_program.is_empty());
         println!("${:?}", rio_program.is_ok());
-        assert_eq!(rio_program.get(), "HELLO WORLD!!");
+        assert_right!(rio_program, "HELLO WORLD!!");
     }
 
     #[test]
@@ -598,7 +895,7 @@ mod tests {
         };
         println!("${:?}", rio_program.is_empty());
         println!("${:?}", rio_program.is_ok());
-        assert_eq!(rio_program.get(), "hello world!!");
+        assert_right!(rio_program, "hello world!!");
     }
 
     #[test]
@@ -618,7 +915,7 @@ mod tests {
         };
         println!("${:?}", rio_program.is_empty());
         println!("${:?}", rio_program.is_ok());
-        assert_eq!(rio_program.get(), "HELLO WORLD!!");
+        assert_right!(rio_program, "HELLO WORLD!!");
     }
 
     #[test]
@@ -630,7 +927,7 @@ mod tests {
         };
         println!("${:?}", rio_program.is_empty());
         println!("${:?}", rio_program.is_ok());
-        assert_eq!(rio_program.get(), "hello world!!");
+        assert_right!(rio_program, "hello world!!");
     }
 
     #[test]
@@ -642,7 +939,7 @@ mod tests {
         };
         println!("${:?}", rio_program.is_empty());
         println!("${:?}", rio_program.is_ok());
-        assert_eq!(rio_program.get(), "hello world!!");
+        assert_right!(rio_program, "hello world!!");
     }
 
     #[test]
@@ -654,7 +951,7 @@ mod tests {
         };
         println!("${:?}", rio_program.is_empty());
         println!("${:?}", rio_program.is_ok());
-        assert_eq!(rio_program.get(), "hello world!!");
+        assert_right!(rio_program, "hello world!!");
     }
 
     #[test]
@@ -666,7 +963,7 @@ mod tests {
         };
         println!("${:?}", rio_program.is_empty());
         println!("${:?}", rio_program.is_ok());
-        assert_eq!(rio_program.get(), "hello world!!");
+        assert_right!(rio_program, "hello world!!");
     }
 
     #[test]
@@ -678,7 +975,7 @@ mod tests {
         };
         println!("${:?}", rio_program.is_empty());
         println!("${:?}", rio_program.is_ok());
-        assert_eq!(rio_program.get(), "hello world!!");
+        assert_right!(rio_program, "hello world!!");
     }
 
     #[test]
@@ -716,7 +1013,7 @@ mod tests {
         };
         println!("${:?}", rio_program.is_empty());
         println!("${:?}", rio_program.is_ok());
-        assert_eq!(rio_program.get(), "hello world!!");
+        assert_right!(rio_program, "hello world!!");
     }
 
     #[test]
@@ -740,7 +1037,7 @@ mod tests {
         };
         println!("${:?}", rio_program.is_empty());
         println!("${:?}", rio_program.is_ok());
-        assert_eq!(rio_program.get(), "hello world!!");
+        assert_right!(rio_program, "hello world!!");
     }
 
     #[test]
@@ -766,7 +1063,7 @@ mod tests {
         };
         println!("${:?}", rio_program.is_empty());
         println!("${:?}", rio_program.is_ok());
-        assert_eq!(rio_program.get(), "hello world!!");
+        assert_right!(rio_program, "hello world!!");
     }
 
     #[test]
@@ -776,13 +1073,117 @@ mod tests {
         parallel_tasks.push(|| RustIO::from_result(Ok(" world".to_string())));
         parallel_tasks.push(|| RustIO::of("!!".to_string()));
 
-        let rio_program: RustIO<String, String> = rust_io! {
+        let rio_program: RustIO<String, TaskError<String>> = rust_io! {
              v <- RustIO::parallel(parallel_tasks,|tasks| RustIO::of(tasks.into_iter().collect()));
              yield v;
         };
         println!("${:?}", rio_program.is_empty());
         println!("${:?}", rio_program.is_ok());
-        assert_eq!(rio_program.get(), "hello world!!");
+        assert_right!(rio_program, "hello world!!");
+    }
+
+    #[test]
+    fn rio_guard() {
+        let rio_program: RustIO<String, String> = rust_io! {
+             v <- RustIO::from_option(Some(String::from("hello")));
+             if v.len() > 3;
+             t <- RustIO::of(String::from(" world!!"));
+             yield v + &t;
+        };
+        assert_right!(rio_program, "hello world!!");
+
+        let rio_program: RustIO<String, String> = rust_io! {
+             v <- RustIO::from_option(Some(String::from("hello")));
+             if v.len() > 10;
+             yield v;
+        };
+        assert!(rio_program.is_empty());
+    }
+
+    #[test]
+    fn rio_pattern_bind() {
+        let rio_program: RustIO<String, String> = rust_io! {
+             (v, t) <- RustIO::of((String::from("hello"), String::from(" world")));
+             Some(i) <- RustIO::of(Some(String::from("!!")));
+             yield v + &t + &i;
+        };
+        assert_right!(rio_program, "hello world!!");
+    }
+
+    #[test]
+    fn rio_pattern_bind_mismatch() {
+        let rio_program: RustIO<String, String> = rust_io! {
+             v <- RustIO::of(String::from("hello"));
+             Some(t) <- RustIO::of(None::<String>);
+             yield v + &t;
+        };
+        assert!(rio_program.is_empty());
+
+        let rio_program: RustIO<i32, String> = rust_io! {
+             std::result::Result::Ok(v) <- RustIO::of(Err::<i32, i32>(503));
+             yield v;
+        };
+        assert!(rio_program.is_empty());
+    }
+
+    #[test]
+    fn rio_recover_clause() {
+        let rio_program: RustIO<String, String> = rust_io! {
+             v <- RustIO::from_option(Some(String::from("hello")));
+             t <- RustIO::<String, String>::from_result(Err(String::from("burning world!!")));
+             yield v + &t;
+             recover { String::from("hello world!!") }
+        };
+        assert_right!(rio_program, "hello world!!");
+    }
+
+    #[test]
+    fn rio_on_error_clause() {
+        let errors = std::cell::RefCell::new(vec!());
+        let rio_program: RustIO<String, String> = rust_io! {
+             v <- RustIO::from_option(Some(String::from("hello")));
+             t <- RustIO::<String, String>::from_result(Err(String::from("burning world!!")));
+             yield v + &t;
+             on_error |e| { errors.borrow_mut().push(e.clone()) }
+        };
+        assert_wrong!(rio_program, "burning world!!");
+        assert_eq!(errors.into_inner(), vec![String::from("burning world!!")]);
+    }
+
+    #[test]
+    fn rio_parallel_error() {
+        let parallel_tasks: Vec<fn() -> RustIO<String, String>> = vec![
+            || RustIO::from_option(Some("hello".to_string())),
+            || RustIO::from_result(Err("burning world!!".to_string())),
+            || RustIO::from_option(None),
+        ];
+        let rio_program = RustIO::parallel(parallel_tasks, |tasks| RustIO::of(tasks.into_iter().collect()));
+        assert_wrong!(rio_program, TaskError::Failed("burning world!!".to_string()));
+    }
+
+    #[test]
+    fn rio_parallel_panic() {
+        let parallel_tasks: Vec<Box<dyn FnOnce() -> RustIO<String, String> + Send>> = vec![
+            Box::new(|| RustIO::of("hello".to_string())),
+            Box::new(|| panic!("burning world!!")),
+            Box::new(|| RustIO::of("world".to_string())),
+        ];
+        let rio_program = RustIO::parallel(parallel_tasks, |tasks| RustIO::of(tasks.into_iter().collect()));
+        assert_wrong!(rio_program, TaskError::Panicked("burning world!!".to_string()));
+    }
+
+    #[test]
+    fn rio_parallel_concurrent() {
+        let parallel_tasks: Vec<fn() -> RustIO<String, String>> = vec![
+            || RustIO::of("hello".to_string()).delay(Duration::from_millis(200)),
+            || RustIO::of(" world".to_string()).delay(Duration::from_millis(200)),
+        ];
+        let start = Instant::now();
+        let rio_program = RustIO::parallel(parallel_tasks, |tasks| RustIO::of(tasks.into_iter().collect()));
+        assert_right!(rio_program, "hello world".to_string());
+        if thread::available_parallelism().map_or(1, NonZeroUsize::get) > 1 {
+            assert!(start.elapsed() < Duration::from_millis(400));
+        }
     }
 
     #[test]
@@ -794,7 +1195,68 @@ mod tests {
         };
         println!("${:?}", rio_program.is_empty());
         println!("${:?}", rio_program.is_ok());
-        assert_eq!(rio_program.failed(), "Error B");
+        assert_wrong!(rio_program, "Error B");
+    }
+
+    #[test]
+    fn rio_map_other_type() {
+        let rio_program: RustIO<usize, String> = rust_io! {
+             v <- RustIO::from_option(Some(String::from("hello world!!")))
+                        .map(|v| v.len());
+             yield v;
+        };
+        println!("${:?}", rio_program.is_empty());
+        println!("${:?}", rio_program.is_ok());
+        assert_right!(rio_program, 13);
+    }
+
+    #[test]
+    fn rio_flat_map_other_type() {
+        let rio_program: RustIO<String, String> = rust_io! {
+             v <- RustIO::from_result(Ok(1981))
+                        .flat_map(|v| RustIO::of(format!("hello {}", v)));
+             yield v;
+        };
+        println!("${:?}", rio_program.is_empty());
+        println!("${:?}", rio_program.is_ok());
+        assert_right!(rio_program, "hello 1981");
+    }
+
+    #[test]
+    fn rio_map_error_other_type() {
+        let rio_program: RustIO<String, i32> = rust_io! {
+             v <- RustIO::from_result(Err(String::from("503")))
+                .map_error(|t| t.parse::<i32>().unwrap());
+            yield v;
+        };
+        println!("${:?}", rio_program.is_empty());
+        println!("${:?}", rio_program.is_ok());
+        assert_wrong!(rio_program, 503);
+    }
+
+    #[test]
+    fn rio_fold_other_type() {
+        let rio_program: RustIO<usize, String> = rust_io! {
+             v <- RustIO::from_option(Some(String::from("hello")))
+                        .fold_into(0, |v| v.len());
+             yield v;
+        };
+        println!("${:?}", rio_program.is_empty());
+        println!("${:?}", rio_program.is_ok());
+        assert_right!(rio_program, 5);
+    }
+
+    #[test]
+    fn rio_zip_other_types() {
+        let rio_program: RustIO<String, String> = rust_io! {
+             v <- RustIO::zip(
+                || RustIO::from_option(Some("hello".to_string())), || RustIO::from_option(Some(1981)),
+                |a,b| RustIO::from_option(Some(format!("{} {}", a, b))));
+            yield v;
+        };
+        println!("${:?}", rio_program.is_empty());
+        println!("${:?}", rio_program.is_ok());
+        assert_right!(rio_program, "hello 1981");
     }
 
     #[test]
@@ -806,7 +1268,7 @@ mod tests {
         };
         println!("${:?}", rio_program.is_empty());
         println!("${:?}", rio_program.is_ok());
-        assert_eq!(rio_program.get(), "hello world!!");
+        assert_right!(rio_program, "hello world!!");
     }
 
     #[test]
@@ -818,7 +1280,7 @@ mod tests {
         };
         println!("${:?}", rio_program.is_empty());
         println!("${:?}", rio_program.is_ok());
-        assert_eq!(rio_program.get(), "hello world!!");
+        assert_right!(rio_program, "hello world!!");
     }
 
     #[test]
@@ -830,7 +1292,7 @@ mod tests {
         };
         println!("${:?}", rio_program.is_empty());
         println!("${:?}", rio_program.is_ok());
-        assert_eq!(rio_program.get(), "hello world!!");
+        assert_right!(rio_program, "hello world!!");
     }
 
     #[test]
@@ -848,7 +1310,7 @@ mod tests {
         };
         println!("${:?}", rio_program.is_empty());
         println!("${:?}", rio_program.is_ok());
-        assert_eq!(rio_program.get(), "HELLO world!!");
+        assert_right!(rio_program, "HELLO world!!");
     }
 
     #[test]
@@ -860,7 +1322,7 @@ mod tests {
         };
         println!("${:?}", rio_program.is_empty());
         println!("${:?}", rio_program.is_ok());
-        assert_eq!(rio_program.get(), "hello world!!");
+        assert_right!(rio_program, "hello world!!");
     }
 
     #[test]
@@ -872,7 +1334,7 @@ mod tests {
         };
         println!("${:?}", rio_program.is_empty());
         println!("${:?}", rio_program.is_ok());
-        assert_eq!(rio_program.get(), "hello world!!");
+        assert_right!(rio_program, "hello world!!");
     }
 
     #[test]
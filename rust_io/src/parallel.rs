@@ -0,0 +1,207 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use crate::{NonEmptyVec, RustIO};
+use crate::RustIO::{Empty, Right, Wrong};
+use crate::settled::Settled;
+
+/// Reason why a task of [parallel_bounded] failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TaskError<T> {
+    /// The task returned [Wrong].
+    Failed(T),
+    /// The task panicked, with the panic message.
+    Panicked(String),
+}
+
+type TaskResult<A, T> = (usize, Result<Settled<A, T>, String>);
+
+impl<A: Send + 'static, T: Send + 'static> RustIO<A, T> {
+    /// Run the tasks in up to [max_parallelism] threads, returning all the values in the order of the tasks.
+    /// A panic in a task is caught as [Panicked], so it does not take down the caller.
+    /// Once a task fails no more tasks are started, and the program is that first error.
+    /// If no task fails, but some of them are [Empty], the program is [Empty].
+    pub fn parallel_bounded<Task: FnOnce() -> Self + Send + 'static>(max_parallelism: usize, tasks: Vec<Task>) -> RustIO<Vec<A>, TaskError<T>> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let total = tasks.len();
+        let receiver = run_tasks(max_parallelism, tasks, stop.clone());
+        let mut values = (0..total).map(|_| None).collect::<Vec<_>>();
+        let mut any_empty = false;
+        for (index, result) in receiver {
+            match result {
+                Ok(Settled::Value(a) | Settled::Right(a)) => values[index] = Some(a),
+                Ok(Settled::Empty) => any_empty = true,
+                Ok(Settled::Wrong(e)) => {
+                    stop.store(true, Ordering::SeqCst);
+                    return Wrong(TaskError::Failed(e));
+                }
+                Err(message) => {
+                    stop.store(true, Ordering::SeqCst);
+                    return Wrong(TaskError::Panicked(message));
+                }
+            }
+        }
+        collect_values(values, any_empty)
+    }
+
+    /// Like [parallel_bounded], but every task runs, and the program keeps all the errors
+    /// in the order of the tasks.
+    pub fn parallel_bounded_all<Task: FnOnce() -> Self + Send + 'static>(max_parallelism: usize, tasks: Vec<Task>) -> RustIO<Vec<A>, NonEmptyVec<TaskError<T>>> {
+        let total = tasks.len();
+        let receiver = run_tasks(max_parallelism, tasks, Arc::new(AtomicBool::new(false)));
+        let mut values = (0..total).map(|_| None).collect::<Vec<_>>();
+        let mut errors = (0..total).map(|_| None).collect::<Vec<_>>();
+        let mut any_empty = false;
+        for (index, result) in receiver {
+            match result {
+                Ok(Settled::Value(a) | Settled::Right(a)) => values[index] = Some(a),
+                Ok(Settled::Empty) => any_empty = true,
+                Ok(Settled::Wrong(e)) => errors[index] = Some(TaskError::Failed(e)),
+                Err(message) => errors[index] = Some(TaskError::Panicked(message)),
+            }
+        }
+        let mut errors = errors.into_iter().flatten();
+        match errors.next() {
+            Some(first) => {
+                let mut all_errors = NonEmptyVec::new(first);
+                errors.for_each(|error| all_errors.push(error));
+                Wrong(all_errors)
+            }
+            None => collect_values(values, any_empty),
+        }
+    }
+}
+
+/// Start the workers, that take the tasks in order until there are no more, or [stop] is set.
+/// The receiver ends once all the workers are done.
+fn run_tasks<A: Send + 'static, T: Send + 'static, Task: FnOnce() -> RustIO<A, T> + Send + 'static>(max_parallelism: usize, tasks: Vec<Task>, stop: Arc<AtomicBool>) -> mpsc::Receiver<TaskResult<A, T>> {
+    let workers = max_parallelism.max(1).min(tasks.len());
+    let queue = Arc::new(Mutex::new(tasks.into_iter().enumerate().collect::<VecDeque<_>>()));
+    let (sender, receiver) = mpsc::channel();
+    for _ in 0..workers {
+        let queue = queue.clone();
+        let stop = stop.clone();
+        let sender = sender.clone();
+        thread::spawn(move || {
+            while !stop.load(Ordering::SeqCst) {
+                let next = queue.lock().unwrap().pop_front();
+                let Some((index, task)) = next else { break };
+                let result = panic::catch_unwind(AssertUnwindSafe(|| Settled::from(task())))
+                    .map_err(panic_message);
+                if sender.send((index, result)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    receiver
+}
+
+fn collect_values<A, E>(values: Vec<Option<A>>, any_empty: bool) -> RustIO<Vec<A>, E> {
+    if any_empty {
+        return Empty();
+    }
+    Right(values.into_iter().flatten().collect())
+}
+
+fn panic_message(cause: Box<dyn Any + Send>) -> String {
+    match cause.downcast::<String>() {
+        Ok(message) => *message,
+        Err(cause) => match cause.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => String::from("task panicked"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::time::{Duration, Instant};
+
+    use crate::Lift;
+
+    use super::*;
+
+    type Task = Box<dyn FnOnce() -> RustIO<String, String> + Send>;
+
+    #[test]
+    fn rio_parallel_bounded() {
+        let tasks: Vec<Task> = (0..6)
+            .map(|i| -> Task { Box::new(move || RustIO::of(i.to_string()).delay(Duration::from_millis(100))) })
+            .collect();
+        let start = Instant::now();
+        let rio_program = RustIO::parallel_bounded(3, tasks);
+        let elapsed = start.elapsed();
+        assert_eq!(rio_program.get(), vec!["0", "1", "2", "3", "4", "5"]);
+        assert!(elapsed >= Duration::from_millis(200) && elapsed < Duration::from_millis(500));
+    }
+
+    #[test]
+    fn rio_parallel_bounded_max_parallelism() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let tasks = (0..8)
+            .map(|i| {
+                let running = running.clone();
+                let max_running = max_running.clone();
+                move || {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_running.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(20));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    RustIO::<i32, String>::of(i)
+                }
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(RustIO::parallel_bounded(2, tasks).get().len(), 8);
+        assert!(max_running.load(Ordering::SeqCst) <= 2);
+    }
+
+    #[test]
+    fn rio_parallel_bounded_first_error() {
+        let tasks: Vec<Task> = vec![
+            Box::new(|| RustIO::of(String::from("hello"))),
+            Box::new(|| RustIO::from_result(Err(String::from("burning world!!")))),
+        ];
+        assert_eq!(RustIO::parallel_bounded(2, tasks).failed(), TaskError::Failed(String::from("burning world!!")));
+    }
+
+    #[test]
+    fn rio_parallel_bounded_panic() {
+        let tasks: Vec<Task> = vec![
+            Box::new(|| RustIO::of(String::from("hello"))),
+            Box::new(|| panic!("burning world!!")),
+        ];
+        assert_eq!(RustIO::parallel_bounded(2, tasks).failed(), TaskError::Panicked(String::from("burning world!!")));
+    }
+
+    #[test]
+    fn rio_parallel_bounded_all_errors() {
+        let tasks: Vec<Task> = vec![
+            Box::new(|| RustIO::from_result(Err(String::from("Error A")))),
+            Box::new(|| RustIO::of(String::from("hello"))),
+            Box::new(|| panic!("Error B")),
+            Box::new(|| RustIO::from_result(Err(String::from("Error C")))),
+        ];
+        assert_eq!(RustIO::parallel_bounded_all(2, tasks).failed().into_vec(), vec![
+            TaskError::Failed(String::from("Error A")),
+            TaskError::Panicked(String::from("Error B")),
+            TaskError::Failed(String::from("Error C")),
+        ]);
+    }
+
+    #[test]
+    fn rio_parallel_bounded_empty() {
+        let tasks: Vec<Task> = vec![
+            Box::new(|| RustIO::of(String::from("hello"))),
+            Box::new(|| RustIO::from_option(None)),
+        ];
+        assert!(RustIO::parallel_bounded(2, tasks).is_empty());
+    }
+}
@@ -0,0 +1,173 @@
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::{Lift, RustIO};
+
+///Lazy version of [RustIO]. Every operator records a new step on top of the previous ones,
+/// and nothing runs until [unsafe_run] is invoked.
+/// Since the program is only a description of the side-effects, it can be run as many times as we want,
+/// getting a new [RustIO] each time.
+/// Functions passed to the operators must be [Fn] so they can be invoked again in every run.
+pub struct Program<A, T> {
+    run: Rc<dyn Fn() -> RustIO<A, T>>,
+}
+
+impl<A, T> Clone for Program<A, T> {
+    fn clone(&self) -> Self {
+        Program { run: self.run.clone() }
+    }
+}
+
+impl<A: 'static, T: 'static> RustIO<A, T> {
+    /// Suspend the creation of a [RustIO] into a [Program], that only runs with [unsafe_run].
+    pub fn suspend<F: Fn() -> RustIO<A, T> + 'static>(f: F) -> Program<A, T> {
+        Program::suspend(f)
+    }
+}
+
+impl<A: 'static, T: 'static> Program<A, T> {
+    pub fn suspend<F: Fn() -> RustIO<A, T> + 'static>(f: F) -> Self {
+        Program { run: Rc::new(f) }
+    }
+
+    /// Run all the steps of the program, returning the [RustIO] of this execution.
+    pub fn unsafe_run(&self) -> RustIO<A, T> {
+        (self.run)()
+    }
+
+    /// Pure value to create a Program without side-effects. Each run returns a copy of the value.
+    pub fn of(a: A) -> Self where A: Clone {
+        Program::suspend(move || RustIO::of(a.clone()))
+    }
+
+    pub fn from_func(f: fn() -> A) -> Self {
+        Program::suspend(move || RustIO::from_func(f))
+    }
+
+    pub fn from_option_func(f: fn() -> Option<A>) -> Self {
+        Program::suspend(move || RustIO::from_option_func(f))
+    }
+
+    pub fn from_result_func(f: fn() -> Result<A, T>) -> Self {
+        Program::suspend(move || RustIO::from_result_func(f))
+    }
+
+    pub fn map<B: 'static, F: Fn(A) -> B + 'static>(self, op: F) -> Program<B, T> {
+        Program::suspend(move || self.unsafe_run().map(&op))
+    }
+
+    pub fn map_error<U: 'static, F: Fn(T) -> U + 'static>(self, op: F) -> Program<A, U> {
+        Program::suspend(move || self.unsafe_run().map_error(&op))
+    }
+
+    pub fn flat_map<B: 'static, F: Fn(A) -> Program<B, T> + 'static>(self, op: F) -> Program<B, T> {
+        Program::suspend(move || self.unsafe_run().flat_map(|a| op(a).unsafe_run()))
+    }
+
+    ///Returns an effect that ignores errors and runs repeatedly until it [at_some_point] succeeds
+    pub fn at_some_point<F: Fn(A) -> Program<A, T> + 'static>(self, op: F) -> Self where A: Clone {
+        Program::suspend(move || self.unsafe_run().at_some_point(|a| op(a).unsafe_run()))
+    }
+
+    pub fn when<P: Fn(&A) -> bool + 'static, F: Fn(A) -> A + 'static>(self, predicate: P, op: F) -> Self {
+        Program::suspend(move || self.unsafe_run().when(&predicate, &op))
+    }
+
+    pub fn zip<B: 'static, C: 'static, F: Fn(A, B) -> Program<C, T> + 'static>(a: Program<A, T>, b: Program<B, T>, op: F) -> Program<C, T> {
+        Program::suspend(move || RustIO::zip(|| a.unsafe_run(), || b.unsafe_run(), |x, y| op(x, y).unsafe_run()))
+    }
+
+    pub fn filter<F: Fn(&A) -> bool + 'static>(self, op: F) -> Self {
+        Program::suspend(move || self.unsafe_run().filter(&op))
+    }
+
+    pub fn fold<B: Clone + 'static, F: Fn(A) -> B + 'static>(self, default: B, op: F) -> Program<B, T> {
+        Program::suspend(move || self.unsafe_run().fold_into(default.clone(), &op))
+    }
+
+    pub fn recover<F: Fn() -> A + 'static>(self, op: F) -> Self {
+        Program::suspend(move || self.unsafe_run().recover(&op))
+    }
+
+    pub fn recover_with<F: Fn() -> Program<A, T> + 'static>(self, op: F) -> Self {
+        Program::suspend(move || self.unsafe_run().recover_with(|| op().unsafe_run()))
+    }
+
+    pub fn delay(self, time: Duration) -> Self {
+        Program::suspend(move || self.unsafe_run().delay(time))
+    }
+
+    pub fn peek<F: Fn(&A) + 'static>(self, op: F) -> Self {
+        Program::suspend(move || self.unsafe_run().peek(&op))
+    }
+
+    pub fn on_error<F: Fn(&T) + 'static>(self, op: F) -> Self {
+        Program::suspend(move || self.unsafe_run().on_error(&op))
+    }
+
+    pub fn on_success<F: Fn(&A) + 'static>(self, op: F) -> Self {
+        Program::suspend(move || self.unsafe_run().on_success(&op))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    #[test]
+    fn program_is_lazy() {
+        let runs = Rc::new(Cell::new(0));
+        let runs_copy = runs.clone();
+        let program: Program<String, String> = RustIO::suspend(move || {
+            runs_copy.set(runs_copy.get() + 1);
+            RustIO::from_option(Some(String::from("hello")))
+        })
+            .map(|v| v + " world!!");
+        assert_eq!(runs.get(), 0);
+        assert_eq!(program.unsafe_run().get(), "hello world!!");
+        assert_eq!(runs.get(), 1);
+    }
+
+    #[test]
+    fn program_run_many_times() {
+        let runs = Rc::new(Cell::new(0));
+        let runs_copy = runs.clone();
+        let program: Program<usize, String> = Program::from_func(|| String::from("hello"))
+            .peek(move |_| runs_copy.set(runs_copy.get() + 1))
+            .flat_map(|v| Program::of(v + " world!!"))
+            .map(|v| v.len());
+        assert_eq!(program.unsafe_run().get(), 13);
+        assert_eq!(program.unsafe_run().get(), 13);
+        assert_eq!(program.clone().unsafe_run().get(), 13);
+        assert_eq!(runs.get(), 3);
+    }
+
+    #[test]
+    fn program_error() {
+        let program: Program<String, i32> = RustIO::suspend(|| RustIO::from_result(Err(503)))
+            .map(|v: String| v.to_uppercase())
+            .map_error(|e| e + 1)
+            .on_error(|e| println!("Error program: ${}", e));
+        assert_eq!(program.unsafe_run().failed(), 504);
+        assert_eq!(program.unsafe_run().failed(), 504);
+    }
+
+    #[test]
+    fn program_filter_recover() {
+        let program: Program<String, String> = Program::of(String::from("hello"))
+            .filter(|v| v.len() > 10)
+            .recover(|| String::from("hello world!!"));
+        assert_eq!(program.unsafe_run().get(), "hello world!!");
+    }
+
+    #[test]
+    fn program_zip() {
+        let program: Program<String, String> = Program::zip(
+            Program::of("hello".to_string()), Program::from_func(|| 1981),
+            |a, b| Program::of(format!("{} {}", a, b)));
+        assert_eq!(program.unsafe_run().get(), "hello 1981");
+        assert_eq!(program.unsafe_run().get(), "hello 1981");
+    }
+}
@@ -0,0 +1,197 @@
+use std::cell::RefCell;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+
+use crate::{Lift, RustIO};
+use crate::RustIO::{Empty, Fut, Right, Value, Wrong};
+
+///Resource defined by the function to [acquire] it and the function to [release] it.
+/// Resources can be composed with [zip], and used with [use_with], that guarantee
+/// [release] runs once the resource is not needed, whatever the result of the use was.
+pub struct Resource<R, T> {
+    acquire: Box<dyn FnOnce() -> RustIO<R, T>>,
+    release: Box<dyn FnOnce(R)>,
+}
+
+impl<A, T> RustIO<A, T> {
+    /// Acquire a resource, use it, and release it.
+    /// If [acquire] succeeds, [release] always runs after [op], either it returns [Right], [Wrong]
+    /// or [Empty], or it panics. In that last case the panic continues once the resource is released.
+    /// If [acquire] is not successful, [op] and [release] are not invoked.
+    pub fn bracket<R, Acq: FnOnce() -> RustIO<R, T>, F: FnOnce(&mut R) -> RustIO<A, T>, Rel: FnOnce(R)>(acquire: Acq, op: F, release: Rel) -> RustIO<A, T> {
+        let mut resource = match acquire() {
+            Value(r) | Right(r) => r,
+            Wrong(e) => return Wrong(e),
+            Empty() => return Empty(),
+            fut @ Fut(_) => fut.join().get(),
+        };
+        let result = panic::catch_unwind(AssertUnwindSafe(|| op(&mut resource)));
+        release(resource);
+        match result {
+            Ok(rio) => rio,
+            Err(cause) => panic::resume_unwind(cause),
+        }
+    }
+}
+
+impl<R: 'static, T: 'static> Resource<R, T> {
+    pub fn make<Acq: FnOnce() -> RustIO<R, T> + 'static, Rel: FnOnce(R) + 'static>(acquire: Acq, release: Rel) -> Self {
+        Resource { acquire: Box::new(acquire), release: Box::new(release) }
+    }
+
+    /// Use the resource with [bracket] semantics.
+    pub fn use_with<A, F: FnOnce(&mut R) -> RustIO<A, T>>(self, op: F) -> RustIO<A, T> {
+        RustIO::bracket(self.acquire, op, self.release)
+    }
+
+    /// Compose two resources into one that acquires both in order, and release them in reverse order.
+    /// If the second one cannot be acquired, the first one is released.
+    pub fn zip<R2: 'static>(self, other: Resource<R2, T>) -> Resource<(R, R2), T> {
+        let release_1 = Rc::new(RefCell::new(Some(self.release)));
+        let release_1_copy = release_1.clone();
+        let acquire_1 = self.acquire;
+        let acquire_2 = other.acquire;
+        let release_2 = other.release;
+        Resource::make(
+            move || acquire_1().flat_map(|r1| {
+                match acquire_2() {
+                    Value(r2) => Value((r1, r2)),
+                    Right(r2) => Right((r1, r2)),
+                    fut @ Fut(_) => Value((r1, fut.join().get())),
+                    failed => {
+                        if let Some(release) = release_1_copy.borrow_mut().take() {
+                            release(r1);
+                        }
+                        match failed {
+                            Wrong(e) => Wrong(e),
+                            _ => Empty(),
+                        }
+                    }
+                }
+            }),
+            move |(r1, r2)| {
+                release_2(r2);
+                if let Some(release) = release_1.borrow_mut().take() {
+                    release(r1);
+                }
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use crate::rust_io;
+
+    use super::*;
+
+    #[test]
+    fn rio_bracket() {
+        let released = Rc::new(Cell::new(false));
+        let released_copy = released.clone();
+        let rio_program: RustIO<String, String> = rust_io! {
+             v <- RustIO::bracket(
+                || RustIO::from_option(Some(String::from("hello"))),
+                |r| RustIO::of(r.clone() + " world!!"),
+                move |_| released_copy.set(true));
+             yield v;
+        };
+        println!("${:?}", rio_program.is_empty());
+        println!("${:?}", rio_program.is_ok());
+        assert_eq!(rio_program.get(), "hello world!!");
+        assert!(released.get());
+    }
+
+    #[test]
+    fn rio_bracket_error() {
+        let released = Rc::new(Cell::new(false));
+        let released_copy = released.clone();
+        let rio_program: RustIO<String, String> = rust_io! {
+             v <- RustIO::bracket(
+                || RustIO::from_option(Some(String::from("hello"))),
+                |_| RustIO::from_result(Err(String::from("burning world!!"))),
+                move |_| released_copy.set(true));
+             yield v;
+        };
+        println!("${:?}", rio_program.is_empty());
+        println!("${:?}", rio_program.is_ok());
+        assert_eq!(rio_program.failed(), "burning world!!");
+        assert!(released.get());
+    }
+
+    #[test]
+    fn rio_bracket_empty() {
+        let released = Rc::new(Cell::new(false));
+        let released_copy = released.clone();
+        let rio_program: RustIO<String, String> = rust_io! {
+             v <- RustIO::bracket(
+                || RustIO::from_option(Some(String::from("hello"))),
+                |r| RustIO::of(r.clone()).filter(|v| v.len() > 10),
+                move |_| released_copy.set(true));
+             yield v;
+        };
+        println!("${:?}", rio_program.is_empty());
+        println!("${:?}", rio_program.is_ok());
+        assert!(rio_program.is_empty());
+        assert!(released.get());
+    }
+
+    #[test]
+    fn rio_bracket_panic() {
+        let released = Rc::new(Cell::new(false));
+        let released_copy = released.clone();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            RustIO::<String, String>::bracket(
+                || RustIO::from_option(Some(String::from("hello"))),
+                |_| panic!("burning world!!"),
+                move |_| released_copy.set(true))
+        }));
+        assert!(result.is_err());
+        assert!(released.get());
+    }
+
+    #[test]
+    fn rio_bracket_acquire_error() {
+        let used = Rc::new(Cell::new(false));
+        let used_copy = used.clone();
+        let released = Rc::new(Cell::new(false));
+        let released_copy = released.clone();
+        let rio_program: RustIO<String, i32> = RustIO::bracket(
+            || RustIO::<String, i32>::from_result(Err(503)),
+            move |r| {
+                used_copy.set(true);
+                RustIO::of(r.clone())
+            },
+            move |_| released_copy.set(true));
+        assert_eq!(rio_program.failed(), 503);
+        assert!(!used.get());
+        assert!(!released.get());
+    }
+
+    #[test]
+    fn rio_resource_zip() {
+        let releases = Rc::new(RefCell::new(vec!()));
+        let releases_1 = releases.clone();
+        let releases_2 = releases.clone();
+        let rio_program: RustIO<String, String> =
+            Resource::make(|| RustIO::of(String::from("hello")), move |r| releases_1.borrow_mut().push(r))
+                .zip(Resource::make(|| RustIO::of(String::from(" world!!")), move |r| releases_2.borrow_mut().push(r)))
+                .use_with(|(a, b)| RustIO::of(a.clone() + b));
+        assert_eq!(rio_program.get(), "hello world!!");
+        assert_eq!(*releases.borrow(), vec![String::from(" world!!"), String::from("hello")]);
+    }
+
+    #[test]
+    fn rio_resource_zip_acquire_error() {
+        let releases = Rc::new(RefCell::new(vec!()));
+        let releases_1 = releases.clone();
+        let releases_2 = releases.clone();
+        let rio_program: RustIO<String, String> =
+            Resource::make(|| RustIO::of(String::from("hello")), move |r| releases_1.borrow_mut().push(r))
+                .zip(Resource::make(|| RustIO::from_result(Err(String::from("burning world!!"))), move |r| releases_2.borrow_mut().push(r)))
+                .use_with(|(a, b)| RustIO::of(a.clone() + b));
+        assert_eq!(rio_program.failed(), "burning world!!");
+        assert_eq!(*releases.borrow(), vec![String::from("hello")]);
+    }
+}
@@ -0,0 +1,200 @@
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::{Lift, RustIO};
+
+///[RustIO] program that depends on an environment [R], like a database handle or a configuration.
+/// Every operator receives the environment, that is only supplied once the program runs with [provide].
+/// Like [Program], nothing runs until then, so the same program can run with the real environment,
+/// or with a mock one in the tests.
+pub struct RIO<R, A, T> {
+    run: Run<R, A, T>,
+}
+
+type Run<R, A, T> = Rc<dyn Fn(&R) -> RustIO<A, T>>;
+
+impl<R, A, T> Clone for RIO<R, A, T> {
+    fn clone(&self) -> Self {
+        RIO { run: self.run.clone() }
+    }
+}
+
+impl<R: 'static, A: 'static, T: 'static> RIO<R, A, T> {
+    /// Program that creates the [RustIO] from the environment.
+    pub fn access<F: Fn(&R) -> RustIO<A, T> + 'static>(f: F) -> Self {
+        RIO { run: Rc::new(f) }
+    }
+
+    /// Program that ignores the environment. Each run returns a copy of the value.
+    pub fn of(a: A) -> Self where A: Clone {
+        RIO::access(move |_| RustIO::of(a.clone()))
+    }
+
+    pub fn from_func(f: fn() -> A) -> Self {
+        RIO::access(move |_| RustIO::from_func(f))
+    }
+
+    pub fn from_option_func(f: fn() -> Option<A>) -> Self {
+        RIO::access(move |_| RustIO::from_option_func(f))
+    }
+
+    pub fn from_result_func(f: fn() -> Result<A, T>) -> Self {
+        RIO::access(move |_| RustIO::from_result_func(f))
+    }
+
+    /// Run the program with the environment, returning the [RustIO] of this execution.
+    pub fn provide(&self, env: R) -> RustIO<A, T> {
+        (self.run)(&env)
+    }
+
+    /// Run the program with a part of a bigger environment [R2], so programs with
+    /// different environments can be combined.
+    pub fn provide_some<R2: 'static, F: Fn(&R2) -> R + 'static>(self, f: F) -> RIO<R2, A, T> {
+        RIO::access(move |env| (self.run)(&f(env)))
+    }
+
+    pub fn map<B: 'static, F: Fn(A) -> B + 'static>(self, op: F) -> RIO<R, B, T> {
+        RIO::access(move |env| (self.run)(env).map(&op))
+    }
+
+    pub fn map_error<U: 'static, F: Fn(T) -> U + 'static>(self, op: F) -> RIO<R, A, U> {
+        RIO::access(move |env| (self.run)(env).map_error(&op))
+    }
+
+    pub fn flat_map<B: 'static, F: Fn(A) -> RIO<R, B, T> + 'static>(self, op: F) -> RIO<R, B, T> {
+        RIO::access(move |env| (self.run)(env).flat_map(|a| (op(a).run)(env)))
+    }
+
+    /// Like [flat_map], but the function receives also the environment.
+    pub fn flat_map_env<B: 'static, F: Fn(&R, A) -> RustIO<B, T> + 'static>(self, op: F) -> RIO<R, B, T> {
+        RIO::access(move |env| (self.run)(env).flat_map(|a| op(env, a)))
+    }
+
+    pub fn zip<B: 'static, C: 'static, F: Fn(A, B) -> RIO<R, C, T> + 'static>(a: RIO<R, A, T>, b: RIO<R, B, T>, op: F) -> RIO<R, C, T> {
+        RIO::access(move |env| RustIO::zip(|| (a.run)(env), || (b.run)(env), |x, y| (op(x, y).run)(env)))
+    }
+
+    pub fn filter<F: Fn(&A) -> bool + 'static>(self, op: F) -> Self {
+        RIO::access(move |env| (self.run)(env).filter(&op))
+    }
+
+    pub fn recover<F: Fn() -> A + 'static>(self, op: F) -> Self {
+        RIO::access(move |env| (self.run)(env).recover(&op))
+    }
+
+    pub fn recover_with<F: Fn() -> RIO<R, A, T> + 'static>(self, op: F) -> Self {
+        RIO::access(move |env| (self.run)(env).recover_with(|| (op().run)(env)))
+    }
+
+    pub fn delay(self, time: Duration) -> Self {
+        RIO::access(move |env| (self.run)(env).delay(time))
+    }
+
+    pub fn peek<F: Fn(&A) + 'static>(self, op: F) -> Self {
+        RIO::access(move |env| (self.run)(env).peek(&op))
+    }
+
+    pub fn on_error<F: Fn(&T) + 'static>(self, op: F) -> Self {
+        RIO::access(move |env| (self.run)(env).on_error(&op))
+    }
+
+    pub fn on_success<F: Fn(&A) + 'static>(self, op: F) -> Self {
+        RIO::access(move |env| (self.run)(env).on_success(&op))
+    }
+}
+
+impl<R: Clone + 'static, T: 'static> RIO<R, R, T> {
+    /// Program that returns a copy of the environment.
+    pub fn environment() -> Self {
+        RIO::access(|env: &R| RustIO::of(env.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// Contract of the dependency that the program receives
+    trait UserRepository {
+        fn find(&self, id: u32) -> Option<String>;
+    }
+
+    struct Env {
+        greeting: String,
+        users: Box<dyn UserRepository>,
+    }
+
+    struct MockRepository {
+        users: HashMap<u32, String>,
+        calls: Rc<RefCell<Vec<u32>>>,
+    }
+
+    impl UserRepository for MockRepository {
+        fn find(&self, id: u32) -> Option<String> {
+            self.calls.borrow_mut().push(id);
+            self.users.get(&id).cloned()
+        }
+    }
+
+    fn find_user(id: u32) -> RIO<Env, String, String> {
+        RIO::access(move |env: &Env| RustIO::from_option(env.users.find(id)))
+    }
+
+    fn greet(id: u32) -> RIO<Env, String, String> {
+        find_user(id)
+            .flat_map_env(|env, name| RustIO::of(format!("{} {}", env.greeting, name)))
+    }
+
+    fn mock_env(greeting: &str, calls: Rc<RefCell<Vec<u32>>>) -> Env {
+        let users = HashMap::from([(1, String::from("Politrons"))]);
+        Env { greeting: greeting.to_string(), users: Box::new(MockRepository { users, calls }) }
+    }
+
+    #[test]
+    fn rio_provide() {
+        let calls = Rc::new(RefCell::new(vec!()));
+        let program = greet(1);
+        assert_eq!(program.provide(mock_env("Hello", calls.clone())).get(), "Hello Politrons");
+        assert_eq!(program.provide(mock_env("Hola", calls.clone())).get(), "Hola Politrons");
+        assert_eq!(*calls.borrow(), vec![1, 1]);
+    }
+
+    #[test]
+    fn rio_provide_empty() {
+        let calls = Rc::new(RefCell::new(vec!()));
+        let program = greet(2).recover(|| String::from("Hello stranger"));
+        assert_eq!(program.provide(mock_env("Hello", calls.clone())).get(), "Hello stranger");
+        assert_eq!(*calls.borrow(), vec![2]);
+    }
+
+    #[test]
+    fn rio_environment() {
+        let program: RIO<String, usize, String> = RIO::environment()
+            .map(|env: String| env.len())
+            .filter(|len| *len > 3);
+        assert_eq!(program.provide(String::from("hello")).get(), 5);
+        assert!(program.provide(String::from("hi")).is_empty());
+    }
+
+    #[test]
+    fn rio_zip_provide_some() {
+        let port: RIO<u16, String, String> = RIO::access(|port: &u16| RustIO::of(port.to_string()));
+        let host: RIO<String, String, String> = RIO::environment();
+        let program: RIO<(String, u16), String, String> = RIO::zip(
+            host.provide_some(|env: &(String, u16)| env.0.clone()),
+            port.provide_some(|env: &(String, u16)| env.1),
+            |host, port| RIO::of(format!("{}:{}", host, port)));
+        assert_eq!(program.provide((String::from("localhost"), 8080)).get(), "localhost:8080");
+    }
+
+    #[test]
+    fn rio_error() {
+        let program: RIO<i32, String, i32> = RIO::access(|code: &i32| RustIO::from_result(Err(*code)))
+            .map(|v: String| v.to_uppercase())
+            .map_error(|e| e + 1);
+        assert_eq!(program.provide(503).failed(), 504);
+    }
+}
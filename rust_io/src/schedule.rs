@@ -0,0 +1,241 @@
+use std::time::Duration;
+
+use rand::{Rng, thread_rng};
+
+///Retry policy used by [retry] operator.
+/// [fixed] wait always the same time between attempts, and [exponential] multiply the wait by [factor]
+/// after each attempt, optionally randomized with [with_jitter] and then capped with [with_max_delay].
+/// Retries stop once [with_max_attempts] or [with_max_elapsed] are reached, or when an uncapped
+/// exponential wait overflows. Without any of them the schedule retries until the task succeeds, like [at_some_point].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schedule {
+    backoff: Backoff,
+    max_delay: Option<Duration>,
+    jitter: f64,
+    max_attempts: Option<u32>,
+    max_elapsed: Option<Duration>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Backoff {
+    Fixed(Duration),
+    Exponential { base: Duration, factor: f64 },
+}
+
+/// Outcome of a [retry] with the number of attempts made, and the reason retrying stopped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryReport {
+    pub attempts: u32,
+    pub stop: RetryStop,
+}
+
+/// Reason why a [retry] stopped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RetryStop {
+    /// The task succeeded.
+    Succeeded,
+    /// The task kept failing after [with_max_attempts] attempts.
+    MaxAttempts,
+    /// Waiting for the next attempt would exceed [with_max_elapsed].
+    MaxElapsed,
+    /// The wait of an [exponential] schedule without [with_max_delay] grew beyond a [Duration].
+    Overflow,
+    /// The monad was not successful, so the task never ran.
+    Skipped,
+    /// The [Fiber] running the retry was cancelled while waiting for the next attempt.
+    Interrupted,
+}
+
+impl Schedule {
+    /// Wait always the same [delay] between attempts.
+    pub fn fixed(delay: Duration) -> Schedule {
+        Schedule {
+            backoff: Backoff::Fixed(delay),
+            max_delay: None,
+            jitter: 0.0,
+            max_attempts: None,
+            max_elapsed: None,
+        }
+    }
+
+    /// Wait [base] after the first attempt, doubling the wait after every attempt.
+    pub fn exponential(base: Duration) -> Schedule {
+        Schedule {
+            backoff: Backoff::Exponential { base, factor: 2.0 },
+            ..Schedule::fixed(base)
+        }
+    }
+
+    /// Multiply the wait by [factor] after every attempt. Only used by [exponential] schedules.
+    pub fn with_factor(self, factor: f64) -> Schedule {
+        match self.backoff {
+            Backoff::Exponential { base, .. } => Schedule { backoff: Backoff::Exponential { base, factor }, ..self },
+            Backoff::Fixed(_) => self,
+        }
+    }
+
+    /// Upper bound for the wait between attempts, jitter included.
+    pub fn with_max_delay(self, max_delay: Duration) -> Schedule {
+        Schedule { max_delay: Some(max_delay), ..self }
+    }
+
+    /// Randomize every wait up to +/- [jitter] of it. [jitter] is clamped between 0 and 1.
+    pub fn with_jitter(self, jitter: f64) -> Schedule {
+        Schedule { jitter: jitter.clamp(0.0, 1.0), ..self }
+    }
+
+    /// Give up after [max_attempts] attempts, counting the first one.
+    pub fn with_max_attempts(self, max_attempts: u32) -> Schedule {
+        Schedule { max_attempts: Some(max_attempts), ..self }
+    }
+
+    /// Give up once the next wait would go beyond [max_elapsed] since the first attempt.
+    pub fn with_max_elapsed(self, max_elapsed: Duration) -> Schedule {
+        Schedule { max_elapsed: Some(max_elapsed), ..self }
+    }
+
+    /// Check if the schedule must stop after [attempts] failed attempts, taking [elapsed] time.
+    /// Otherwise it returns the time to wait before the next attempt.
+    pub(crate) fn next(&self, attempts: u32, elapsed: Duration) -> Result<Duration, RetryStop> {
+        if self.max_attempts.is_some_and(|max_attempts| attempts >= max_attempts) {
+            return Err(RetryStop::MaxAttempts);
+        }
+        let delay = self.delay_after(attempts).ok_or(RetryStop::Overflow)?;
+        if self.max_elapsed.is_some_and(|max_elapsed| elapsed + delay > max_elapsed) {
+            return Err(RetryStop::MaxElapsed);
+        }
+        Ok(delay)
+    }
+
+    /// Wait after [attempts] failed attempts, jittered and then capped with [max_delay].
+    /// [None] if the wait does not fit in a [Duration] and there's no [max_delay] to cap it.
+    fn delay_after(&self, attempts: u32) -> Option<Duration> {
+        let delay = match self.backoff {
+            Backoff::Fixed(delay) => Some(delay),
+            Backoff::Exponential { base, factor } => {
+                let exponent = attempts.saturating_sub(1).min(i32::MAX as u32) as i32;
+                Duration::try_from_secs_f64(base.as_secs_f64() * factor.powi(exponent)).ok()
+            }
+        };
+        let delay = delay.and_then(|delay| {
+            if self.jitter > 0.0 {
+                let ratio = thread_rng().gen_range(1.0 - self.jitter..=1.0 + self.jitter);
+                Duration::try_from_secs_f64(delay.as_secs_f64() * ratio).ok()
+            } else {
+                Some(delay)
+            }
+        });
+        match (delay, self.max_delay) {
+            (Some(delay), Some(max_delay)) => Some(delay.min(max_delay)),
+            (None, Some(max_delay)) => Some(max_delay),
+            (delay, None) => delay,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use crate::{Lift, RustIO};
+
+    use super::*;
+
+    #[test]
+    fn schedule_fixed() {
+        let schedule = Schedule::fixed(Duration::from_millis(10));
+        assert_eq!(schedule.next(1, Duration::ZERO), Ok(Duration::from_millis(10)));
+        assert_eq!(schedule.next(5, Duration::ZERO), Ok(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn schedule_exponential() {
+        let schedule = Schedule::exponential(Duration::from_millis(10))
+            .with_max_delay(Duration::from_millis(50));
+        assert_eq!(schedule.next(1, Duration::ZERO), Ok(Duration::from_millis(10)));
+        assert_eq!(schedule.next(2, Duration::ZERO), Ok(Duration::from_millis(20)));
+        assert_eq!(schedule.next(3, Duration::ZERO), Ok(Duration::from_millis(40)));
+        assert_eq!(schedule.next(4, Duration::ZERO), Ok(Duration::from_millis(50)));
+    }
+
+    #[test]
+    fn schedule_jitter() {
+        let schedule = Schedule::fixed(Duration::from_millis(100)).with_jitter(0.5);
+        for attempt in 1..100 {
+            let delay = schedule.next(attempt, Duration::ZERO).unwrap();
+            assert!(delay >= Duration::from_millis(50) && delay <= Duration::from_millis(150));
+        }
+    }
+
+    #[test]
+    fn schedule_jitter_capped() {
+        let schedule = Schedule::fixed(Duration::from_millis(100))
+            .with_jitter(0.5)
+            .with_max_delay(Duration::from_millis(100));
+        for attempt in 1..100 {
+            let delay = schedule.next(attempt, Duration::ZERO).unwrap();
+            assert!(delay >= Duration::from_millis(50) && delay <= Duration::from_millis(100));
+        }
+    }
+
+    #[test]
+    fn schedule_exponential_overflow() {
+        let schedule = Schedule::exponential(Duration::from_secs(1));
+        assert_eq!(schedule.next(64, Duration::ZERO), Ok(Duration::from_secs(1 << 63)));
+        assert_eq!(schedule.next(65, Duration::ZERO), Err(RetryStop::Overflow));
+        let capped = schedule.with_max_delay(Duration::from_secs(60));
+        assert_eq!(capped.next(65, Duration::ZERO), Ok(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn schedule_limits() {
+        let schedule = Schedule::fixed(Duration::from_millis(100))
+            .with_max_attempts(3)
+            .with_max_elapsed(Duration::from_secs(1));
+        assert_eq!(schedule.next(3, Duration::ZERO), Err(RetryStop::MaxAttempts));
+        assert_eq!(schedule.next(1, Duration::from_millis(950)), Err(RetryStop::MaxElapsed));
+    }
+
+    #[test]
+    fn rio_retry_success() {
+        let attempts = Rc::new(Cell::new(0));
+        let (rio_program, report) = RustIO::<String, String>::of(String::from("hello"))
+            .retry(Schedule::fixed(Duration::from_millis(10)).with_max_attempts(5), |v| {
+                attempts.set(attempts.get() + 1);
+                if attempts.get() < 3 {
+                    RustIO::from_result(Err("Error".to_string()))
+                } else {
+                    RustIO::from_result(Ok(v + " world"))
+                }
+            });
+        assert_eq!(rio_program.get(), "hello world");
+        assert_eq!(report, RetryReport { attempts: 3, stop: RetryStop::Succeeded });
+    }
+
+    #[test]
+    fn rio_retry_max_attempts() {
+        let (rio_program, report) = RustIO::<String, String>::of(String::from("hello"))
+            .retry(Schedule::exponential(Duration::from_millis(1)).with_jitter(0.2).with_max_attempts(4),
+                   |_| RustIO::from_result(Err("Error".to_string())));
+        assert_eq!(rio_program.failed(), "Error");
+        assert_eq!(report, RetryReport { attempts: 4, stop: RetryStop::MaxAttempts });
+    }
+
+    #[test]
+    fn rio_retry_max_elapsed() {
+        let (rio_program, report) = RustIO::<String, String>::of(String::from("hello"))
+            .retry(Schedule::fixed(Duration::from_millis(100)).with_max_elapsed(Duration::from_millis(250)),
+                   |_| RustIO::from_option(None));
+        assert!(rio_program.is_empty());
+        assert_eq!(report, RetryReport { attempts: 3, stop: RetryStop::MaxElapsed });
+    }
+
+    #[test]
+    fn rio_retry_skipped() {
+        let (rio_program, report) = RustIO::<String, String>::from_result(Err("Error".to_string()))
+            .retry(Schedule::fixed(Duration::from_millis(10)), |v| RustIO::of(v + " world"));
+        assert_eq!(rio_program.failed(), "Error");
+        assert_eq!(report, RetryReport { attempts: 0, stop: RetryStop::Skipped });
+    }
+}
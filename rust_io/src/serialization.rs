@@ -0,0 +1,82 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::ser::Error;
+
+use crate::RustIO;
+use crate::RustIO::{Empty, Fut, Right, Value, Wrong};
+
+///Stable representation of [RustIO], tagged with the [state] of the program.
+/// ```json
+/// {"state":"Right","value":"hello"}
+/// {"state":"Wrong","error":"burning world!!"}
+/// {"state":"Value","value":"hello"}
+/// {"state":"Empty"}
+/// ```
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "state")]
+enum Repr<A, T> {
+    Right { value: A },
+    Wrong { error: T },
+    Value { value: A },
+    Empty,
+}
+
+/// A [Fut] cannot be serialized, since it's not finished yet. It must be joined first.
+impl<A: Serialize, T: Serialize> Serialize for RustIO<A, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let repr: Repr<&A, &T> = match self {
+            Right(value) => Repr::Right { value },
+            Wrong(error) => Repr::Wrong { error },
+            Value(value) => Repr::Value { value },
+            Empty() => Repr::Empty,
+            Fut(_) => return Err(S::Error::custom("RustIO::Fut cannot be serialized before it's joined")),
+        };
+        repr.serialize(serializer)
+    }
+}
+
+impl<'de, A: Deserialize<'de>, T: Deserialize<'de>> Deserialize<'de> for RustIO<A, T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::Right { value } => Right(value),
+            Repr::Wrong { error } => Wrong(error),
+            Repr::Value { value } => Value(value),
+            Repr::Empty => Empty(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+
+    use crate::{assert_empty, assert_right, assert_wrong, Lift};
+
+    use super::*;
+
+    #[test]
+    fn rio_serialize() {
+        let rio_program = RustIO::<String, String>::of(String::from("hello"));
+        assert_eq!(serde_json::to_string(&rio_program).unwrap(), r#"{"state":"Value","value":"hello"}"#);
+        let rio_program = RustIO::<String, i32>::from_result(Err(503));
+        assert_eq!(serde_json::to_string(&rio_program).unwrap(), r#"{"state":"Wrong","error":503}"#);
+        let rio_program = RustIO::<String, i32>::from_option(None);
+        assert_eq!(serde_json::to_string(&rio_program).unwrap(), r#"{"state":"Empty"}"#);
+    }
+
+    #[test]
+    fn rio_serialize_fut() {
+        let rio_program: RustIO<String, String> = Fut(async { String::from("hello") }.boxed_local());
+        assert!(serde_json::to_string(&rio_program).is_err());
+    }
+
+    #[test]
+    fn rio_deserialize() {
+        let rio_program: RustIO<Vec<i32>, String> = serde_json::from_str(r#"{"state":"Right","value":[1,2,3]}"#).unwrap();
+        assert_right!(rio_program, vec![1, 2, 3]);
+        let rio_program: RustIO<Vec<i32>, String> = serde_json::from_str(r#"{"state":"Wrong","error":"burning world!!"}"#).unwrap();
+        assert_wrong!(rio_program, "burning world!!");
+        let rio_program: RustIO<Vec<i32>, String> = serde_json::from_str(r#"{"state":"Empty"}"#).unwrap();
+        assert_empty!(rio_program);
+        assert!(serde_json::from_str::<RustIO<i32, String>>(r#"{"state":"Fut"}"#).is_err());
+    }
+}
@@ -0,0 +1,42 @@
+use futures::executor::block_on;
+
+use crate::RustIO;
+
+/// [Send] copy of the final states of [RustIO], used to move results between tasks and threads.
+/// [Fut] is not part of it, since it's joined before leaving the current thread.
+pub(crate) enum Settled<A, T> {
+    Right(A),
+    Wrong(T),
+    Value(A),
+    Empty,
+}
+
+impl<A, T> From<RustIO<A, T>> for Settled<A, T> {
+    fn from(rio: RustIO<A, T>) -> Self {
+        match rio {
+            RustIO::Right(a) => Settled::Right(a),
+            RustIO::Wrong(t) => Settled::Wrong(t),
+            RustIO::Value(a) => Settled::Value(a),
+            RustIO::Empty() => Settled::Empty,
+            RustIO::Fut(fut_box) => Settled::Value(block_on(fut_box)),
+        }
+    }
+}
+
+impl<A, T> From<Settled<A, T>> for RustIO<A, T> {
+    fn from(settled: Settled<A, T>) -> Self {
+        match settled {
+            Settled::Right(a) => RustIO::Right(a),
+            Settled::Wrong(t) => RustIO::Wrong(t),
+            Settled::Value(a) => RustIO::Value(a),
+            Settled::Empty => RustIO::Empty(),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<A, T> Settled<A, T> {
+    pub(crate) fn is_ok(&self) -> bool {
+        matches!(self, Settled::Value(_) | Settled::Right(_))
+    }
+}
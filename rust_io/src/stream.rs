@@ -0,0 +1,221 @@
+use std::sync::mpsc::Receiver;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{Lift, RustIO};
+use crate::RustIO::{Empty, Fut, Right, Value, Wrong};
+
+///Effect with many values, like the pages of an API, or the records of a topic.
+/// The stream is lazy, and the values are pulled one by one once it runs with [run_collect] or [fold].
+/// [Empty] values are skipped, and a [Wrong] value ends the run with that error.
+pub struct RustIOStream<A, T> {
+    values: Box<dyn Iterator<Item=Result<A, T>>>,
+}
+
+impl<A: 'static, T: 'static> RustIOStream<A, T> {
+    /// Stream of all the values of the iterator, in order.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_iter<I: IntoIterator<Item=A> + 'static>(iter: I) -> Self {
+        RustIOStream { values: Box::new(iter.into_iter().map(Ok)) }
+    }
+
+    /// Stream of the values of every [RustIO]. Each one is created only once the previous one is consumed.
+    pub fn from_rio_iter<I: IntoIterator<Item=RustIO<A, T>> + 'static>(iter: I) -> Self {
+        RustIOStream {
+            values: Box::new(iter.into_iter().filter_map(|rio| match rio {
+                Value(a) | Right(a) => Some(Ok(a)),
+                Wrong(e) => Some(Err(e)),
+                Empty() => None,
+                fut @ Fut(_) => Some(Ok(fut.join().get())),
+            }))
+        }
+    }
+
+    /// Stream of the values received in the channel, that ends once all the senders are dropped.
+    pub fn from_channel(receiver: Receiver<A>) -> Self {
+        RustIOStream { values: Box::new(receiver.into_iter().map(Ok)) }
+    }
+
+    pub fn map<B: 'static, F: FnMut(A) -> B + 'static>(self, mut op: F) -> RustIOStream<B, T> {
+        RustIOStream { values: Box::new(self.values.map(move |value| value.map(&mut op))) }
+    }
+
+    pub fn map_error<U: 'static, F: FnMut(T) -> U + 'static>(self, mut op: F) -> RustIOStream<A, U> {
+        RustIOStream { values: Box::new(self.values.map(move |value| value.map_err(&mut op))) }
+    }
+
+    pub fn filter<F: FnMut(&A) -> bool + 'static>(self, mut op: F) -> Self {
+        RustIOStream {
+            values: Box::new(self.values.filter(move |value| match value {
+                Ok(a) => op(a),
+                Err(_) => true,
+            }))
+        }
+    }
+
+    /// Replace every value with all the values of the stream returned by the function.
+    pub fn flat_map<B: 'static, F: FnMut(A) -> RustIOStream<B, T> + 'static>(self, mut op: F) -> RustIOStream<B, T> {
+        RustIOStream {
+            values: Box::new(self.values.flat_map(move |value| -> Box<dyn Iterator<Item=Result<B, T>>> {
+                match value {
+                    Ok(a) => op(a).values,
+                    Err(e) => Box::new(std::iter::once(Err(e))),
+                }
+            }))
+        }
+    }
+
+    /// Only the first [n] values of the stream.
+    pub fn take(self, n: usize) -> Self {
+        RustIOStream { values: Box::new(self.values.take(n)) }
+    }
+
+    /// Group the values in vectors of [size] values. The last one can be smaller.
+    /// An error is emitted as soon as it's found, after the values grouped before it.
+    pub fn chunk(self, size: usize) -> RustIOStream<Vec<A>, T> {
+        let size = size.max(1);
+        let mut values = self.values;
+        let mut error = None;
+        RustIOStream {
+            values: Box::new(std::iter::from_fn(move || {
+                if let Some(e) = error.take() {
+                    return Some(Err(e));
+                }
+                let mut chunk = Vec::with_capacity(size);
+                while chunk.len() < size {
+                    match values.next() {
+                        Some(Ok(a)) => chunk.push(a),
+                        Some(Err(e)) if chunk.is_empty() => return Some(Err(e)),
+                        Some(Err(e)) => {
+                            error = Some(e);
+                            break;
+                        }
+                        None => break,
+                    }
+                }
+                if chunk.is_empty() { None } else { Some(Ok(chunk)) }
+            }))
+        }
+    }
+
+    /// Emit at most one value every [time], waiting before a value when it comes too early.
+    pub fn throttle(self, time: Duration) -> Self {
+        let mut values = self.values;
+        let mut last: Option<Instant> = None;
+        RustIOStream {
+            values: Box::new(std::iter::from_fn(move || {
+                let value = values.next()?;
+                if let Some(elapsed) = last.map(|instant| instant.elapsed()) {
+                    if elapsed < time {
+                        thread::sleep(time - elapsed);
+                    }
+                }
+                last = Some(Instant::now());
+                Some(value)
+            }))
+        }
+    }
+
+    /// Run the stream, combining all the values. The first error stops the run.
+    pub fn fold<B, F: FnMut(B, A) -> B>(self, default: B, mut op: F) -> RustIO<B, T> {
+        let mut acc = default;
+        for value in self.values {
+            match value {
+                Ok(a) => acc = op(acc, a),
+                Err(e) => return Wrong(e),
+            }
+        }
+        Right(acc)
+    }
+
+    /// Run the stream, returning all the values in order. The first error stops the run.
+    pub fn run_collect(self) -> RustIO<Vec<A>, T> {
+        self.fold(vec!(), |mut values, a| {
+            values.push(a);
+            values
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::sync::mpsc;
+
+    use super::*;
+
+    fn fetch_page(page: u32) -> RustIO<Vec<String>, String> {
+        match page {
+            0..=2 => RustIO::of((0..3).map(|i| format!("user-{}-{}", page, i)).collect()),
+            3 => RustIO::from_option(None),
+            _ => RustIO::from_result(Err(format!("page {} not found", page))),
+        }
+    }
+
+    #[test]
+    fn rio_stream_run_collect() {
+        let rio_program = RustIOStream::<i32, String>::from_iter(1..10)
+            .map(|v| v * 10)
+            .filter(|v| v % 20 == 0)
+            .run_collect();
+        assert_eq!(rio_program.get(), vec![20, 40, 60, 80]);
+    }
+
+    #[test]
+    fn rio_stream_flat_map_pages() {
+        let rio_program = RustIOStream::<u32, String>::from_iter(0..)
+            .flat_map(|page| RustIOStream::from_rio_iter(vec![fetch_page(page)]))
+            .flat_map(RustIOStream::from_iter)
+            .take(7)
+            .run_collect();
+        assert_eq!(rio_program.get().last().unwrap(), "user-2-0");
+    }
+
+    #[test]
+    fn rio_stream_error() {
+        let fetched = Rc::new(Cell::new(0));
+        let fetched_copy = fetched.clone();
+        let rio_program = RustIOStream::from_rio_iter((0..10).map(move |page| {
+            fetched_copy.set(fetched_copy.get() + 1);
+            fetch_page(page)
+        }))
+            .map(|users| users.len())
+            .fold(0, |total, users| total + users);
+        assert_eq!(rio_program.failed(), "page 4 not found");
+        assert_eq!(fetched.get(), 5);
+    }
+
+    #[test]
+    fn rio_stream_chunk() {
+        let rio_program = RustIOStream::<i32, String>::from_iter(1..8)
+            .chunk(3)
+            .run_collect();
+        assert_eq!(rio_program.get(), vec![vec![1, 2, 3], vec![4, 5, 6], vec![7]]);
+    }
+
+    #[test]
+    fn rio_stream_chunk_error() {
+        let rio_program = RustIOStream::from_rio_iter(vec![RustIO::of(1), RustIO::of(2), RustIO::from_result(Err(String::from("burning world!!")))])
+            .chunk(3)
+            .map(|chunk| chunk.len())
+            .run_collect();
+        assert_eq!(rio_program.failed(), "burning world!!");
+    }
+
+    #[test]
+    fn rio_stream_channel_throttle() {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for i in 0..5 {
+                sender.send(i).unwrap();
+            }
+        });
+        let start = Instant::now();
+        let rio_program = RustIOStream::<i32, String>::from_channel(receiver)
+            .throttle(Duration::from_millis(50))
+            .run_collect();
+        assert_eq!(rio_program.get(), vec![0, 1, 2, 3, 4]);
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+}
@@ -0,0 +1,130 @@
+//! Assertions for [RustIO] values, that show the state of the program when it's not the expected one.
+//! ```
+//! use rust_io::{assert_right, Lift, RustIO};
+//!
+//! let rio_program: RustIO<String, String> = RustIO::of(String::from("hello"));
+//! assert_right!(rio_program, "hello");
+//! ```
+use std::fmt::Debug;
+
+use futures::executor::block_on;
+
+use crate::RustIO;
+use crate::RustIO::{Empty, Fut, Right, Value, Wrong};
+
+pub use crate::{assert_empty, assert_right, assert_wrong};
+
+/// Join a [Fut] so the final state of the program can be checked.
+#[doc(hidden)]
+pub fn settle<A, T>(rio: RustIO<A, T>) -> RustIO<A, T> {
+    match rio {
+        Fut(fut_box) => Value(block_on(fut_box)),
+        other => other,
+    }
+}
+
+/// Description of the state of the program used in the assertion messages.
+pub fn describe<A: Debug, T: Debug>(rio: &RustIO<A, T>) -> String {
+    match rio {
+        Right(a) => format!("Right({:?})", a),
+        Value(a) => format!("Value({:?})", a),
+        Wrong(e) => format!("Wrong({:?})", e),
+        Empty() => String::from("Empty"),
+        Fut(_) => String::from("Fut"),
+    }
+}
+
+/// Assert the program is [Value] or [Right], returning the value.
+/// With a second argument, the value is compared with [assert_eq].
+#[macro_export]
+macro_rules! assert_right {
+    ($rio:expr $(,)?) => {
+        match $crate::testing::settle($rio) {
+            $crate::RustIO::Value(a) | $crate::RustIO::Right(a) => a,
+            other => panic!("assertion failed: expected Right, found {}", $crate::testing::describe(&other)),
+        }
+    };
+    ($rio:expr, $expected:expr $(,)?) => {
+        assert_eq!($crate::assert_right!($rio), $expected, "RustIO is Right, but not with the expected value")
+    };
+}
+
+/// Assert the program is [Wrong], returning the error.
+/// With a second argument, the error is compared with [assert_eq].
+#[macro_export]
+macro_rules! assert_wrong {
+    ($rio:expr $(,)?) => {
+        match $crate::testing::settle($rio) {
+            $crate::RustIO::Wrong(e) => e,
+            other => panic!("assertion failed: expected Wrong, found {}", $crate::testing::describe(&other)),
+        }
+    };
+    ($rio:expr, $expected:expr $(,)?) => {
+        assert_eq!($crate::assert_wrong!($rio), $expected, "RustIO is Wrong, but not with the expected error")
+    };
+}
+
+/// Assert the program is [Empty].
+#[macro_export]
+macro_rules! assert_empty {
+    ($rio:expr $(,)?) => {
+        match $crate::testing::settle($rio) {
+            $crate::RustIO::Empty() => (),
+            other => panic!("assertion failed: expected Empty, found {}", $crate::testing::describe(&other)),
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+
+    use crate::Lift;
+
+    use super::*;
+
+    fn panic_message<F: FnOnce() + panic::UnwindSafe>(op: F) -> String {
+        let cause = panic::catch_unwind(op).unwrap_err();
+        match cause.downcast::<String>() {
+            Ok(message) => *message,
+            Err(_) => String::new(),
+        }
+    }
+
+    #[test]
+    fn rio_assert_right() {
+        assert_right!(RustIO::<String, String>::of(String::from("hello")), "hello");
+        assert_eq!(assert_right!(RustIO::<i32, String>::from_result(Ok(1981))), 1981);
+        let message = panic_message(|| {
+            assert_right!(RustIO::<String, i32>::from_result(Err(503)), "hello");
+        });
+        assert_eq!(message, "assertion failed: expected Right, found Wrong(503)");
+    }
+
+    #[test]
+    fn rio_assert_right_diff() {
+        let message = panic_message(|| {
+            assert_right!(RustIO::<String, String>::of(String::from("hello")), "world");
+        });
+        assert!(message.contains("left: \"hello\""));
+        assert!(message.contains("right: \"world\""));
+    }
+
+    #[test]
+    fn rio_assert_wrong() {
+        assert_wrong!(RustIO::<String, i32>::from_result(Err(503)), 503);
+        let message = panic_message(|| {
+            assert_wrong!(RustIO::<String, i32>::of(String::from("hello")), 503);
+        });
+        assert_eq!(message, "assertion failed: expected Wrong, found Value(\"hello\")");
+    }
+
+    #[test]
+    fn rio_assert_empty() {
+        assert_empty!(RustIO::<String, String>::from_option(None));
+        let message = panic_message(|| {
+            assert_empty!(RustIO::<String, String>::from_option(Some(String::from("hello"))));
+        });
+        assert_eq!(message, "assertion failed: expected Empty, found Value(\"hello\")");
+    }
+}
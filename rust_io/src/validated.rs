@@ -0,0 +1,235 @@
+use crate::{Lift, RustIO};
+use crate::RustIO::{Empty, Fut, Right, Value, Wrong};
+
+///Vector with at least one element, used to keep all the errors of a [Validated].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NonEmptyVec<T> {
+    head: T,
+    tail: Vec<T>,
+}
+
+impl<T> NonEmptyVec<T> {
+    pub fn new(head: T) -> Self {
+        NonEmptyVec { head, tail: vec!() }
+    }
+
+    pub fn push(&mut self, t: T) {
+        self.tail.push(t);
+    }
+
+    pub fn append(&mut self, other: NonEmptyVec<T>) {
+        self.tail.push(other.head);
+        self.tail.extend(other.tail);
+    }
+
+    pub fn first(&self) -> &T {
+        &self.head
+    }
+
+    pub fn len(&self) -> usize {
+        self.tail.len() + 1
+    }
+
+    /// Always [false], but kept along [len] for consistency with other collections.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=&T> {
+        std::iter::once(&self.head).chain(self.tail.iter())
+    }
+
+    pub fn map<U, F: FnMut(T) -> U>(self, mut op: F) -> NonEmptyVec<U> {
+        NonEmptyVec { head: op(self.head), tail: self.tail.into_iter().map(op).collect() }
+    }
+
+    pub fn into_vec(self) -> Vec<T> {
+        let mut vec = vec![self.head];
+        vec.extend(self.tail);
+        vec
+    }
+}
+
+impl<T> IntoIterator for NonEmptyVec<T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.into_vec().into_iter()
+    }
+}
+
+///Accumulating version of [RustIO] for validations.
+/// Where [RustIO] stops in the first [Wrong], [zip] and [zip_all] run every validation
+/// and keep all the errors found in [Invalid].
+/// Once the validation is done, [into_rust_io] returns to [RustIO] with all the errors.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Validated<A, T> {
+    Valid(A),
+    Invalid(NonEmptyVec<T>),
+    Empty(),
+}
+
+/// [Value] and [Right] are [Valid], [Wrong] is [Invalid] with one error, and a [Fut] is joined first.
+impl<A, T> From<RustIO<A, T>> for Validated<A, T> {
+    fn from(rio: RustIO<A, T>) -> Self {
+        match rio {
+            Value(a) | Right(a) => Validated::Valid(a),
+            Wrong(t) => Validated::Invalid(NonEmptyVec::new(t)),
+            Empty() => Validated::Empty(),
+            fut @ Fut(_) => Validated::from(fut.join()),
+        }
+    }
+}
+
+impl<A, T> Validated<A, T> {
+    pub fn valid(a: A) -> Self {
+        Validated::Valid(a)
+    }
+
+    pub fn invalid(t: T) -> Self {
+        Validated::Invalid(NonEmptyVec::new(t))
+    }
+
+    pub fn is_valid(&self) -> bool {
+        matches!(self, Validated::Valid(_))
+    }
+
+    pub fn map<B, F: FnOnce(A) -> B>(self, op: F) -> Validated<B, T> {
+        match self {
+            Validated::Valid(a) => Validated::Valid(op(a)),
+            Validated::Invalid(errors) => Validated::Invalid(errors),
+            Validated::Empty() => Validated::Empty(),
+        }
+    }
+
+    /// Transform every error of the validation.
+    pub fn map_error<U, F: FnMut(T) -> U>(self, op: F) -> Validated<A, U> {
+        match self {
+            Validated::Valid(a) => Validated::Valid(a),
+            Validated::Invalid(errors) => Validated::Invalid(errors.map(op)),
+            Validated::Empty() => Validated::Empty(),
+        }
+    }
+
+    /// Dependent validation, that only runs when the current one is [Valid].
+    pub fn and_then<B, F: FnOnce(A) -> Validated<B, T>>(self, op: F) -> Validated<B, T> {
+        match self {
+            Validated::Valid(a) => op(a),
+            Validated::Invalid(errors) => Validated::Invalid(errors),
+            Validated::Empty() => Validated::Empty(),
+        }
+    }
+
+    /// Combine two independent validations. If both are [Invalid] the errors of both are kept.
+    /// Any [Invalid] wins over [Empty].
+    pub fn zip<B, C, F: FnOnce(A, B) -> C>(a: Validated<A, T>, b: Validated<B, T>, op: F) -> Validated<C, T> {
+        match (a, b) {
+            (Validated::Valid(x), Validated::Valid(y)) => Validated::Valid(op(x, y)),
+            (Validated::Invalid(mut errors), Validated::Invalid(more_errors)) => {
+                errors.append(more_errors);
+                Validated::Invalid(errors)
+            }
+            (Validated::Invalid(errors), _) | (_, Validated::Invalid(errors)) => Validated::Invalid(errors),
+            _ => Validated::Empty(),
+        }
+    }
+
+    /// Combine all the validations, keeping every value in order, or every error found.
+    pub fn zip_all<I: IntoIterator<Item=Validated<A, T>>>(validations: I) -> Validated<Vec<A>, T> {
+        validations.into_iter()
+            .fold(Validated::Valid(vec!()), |values, validation| {
+                Validated::zip(values, validation, |mut values, value| {
+                    values.push(value);
+                    values
+                })
+            })
+    }
+
+    /// Back to [RustIO] once the validation is done. [Valid] is [Right], and [Invalid] is [Wrong]
+    /// with all the errors.
+    pub fn into_rust_io(self) -> RustIO<A, NonEmptyVec<T>> {
+        match self {
+            Validated::Valid(a) => Right(a),
+            Validated::Invalid(errors) => Wrong(errors),
+            Validated::Empty() => Empty(),
+        }
+    }
+}
+
+impl<A, T> RustIO<A, T> {
+    /// Run all the programs, and keep every value, or all the errors found, instead of only the first one.
+    pub fn zip_all<I: IntoIterator<Item=RustIO<A, T>>>(rios: I) -> RustIO<Vec<A>, NonEmptyVec<T>> {
+        Validated::zip_all(rios.into_iter().map(Validated::from)).into_rust_io()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate_name(name: &str) -> RustIO<String, String> {
+        if name.is_empty() {
+            RustIO::from_result(Err(String::from("name is empty")))
+        } else {
+            RustIO::from_result(Ok(name.to_string()))
+        }
+    }
+
+    fn validate_age(age: i32) -> RustIO<i32, String> {
+        if age < 18 {
+            RustIO::from_result(Err(format!("age {} is under 18", age)))
+        } else {
+            RustIO::from_result(Ok(age))
+        }
+    }
+
+    #[test]
+    fn validated_zip() {
+        let rio_program = Validated::zip(
+            Validated::from(validate_name("Politrons")), Validated::from(validate_age(40)),
+            |name, age| format!("{} {}", name, age))
+            .into_rust_io();
+        assert_eq!(rio_program.get(), "Politrons 40");
+    }
+
+    #[test]
+    fn validated_zip_errors() {
+        let rio_program = Validated::zip(
+            Validated::from(validate_name("")), Validated::from(validate_age(10)),
+            |name, age| format!("{} {}", name, age))
+            .into_rust_io();
+        assert_eq!(rio_program.failed().into_vec(), vec![String::from("name is empty"), String::from("age 10 is under 18")]);
+    }
+
+    #[test]
+    fn validated_zip_all() {
+        let validated = Validated::zip_all(vec![
+            Validated::from(validate_age(20)),
+            Validated::from(validate_age(10)),
+            Validated::from(validate_age(30)),
+            Validated::from(validate_age(5)),
+        ]);
+        assert_eq!(validated, Validated::Invalid(NonEmptyVec { head: String::from("age 10 is under 18"), tail: vec![String::from("age 5 is under 18")] }));
+    }
+
+    #[test]
+    fn validated_map_error() {
+        let validated = Validated::<String, String>::invalid(String::from("name is empty"))
+            .map(|name| name.len())
+            .map_error(|e| e.to_uppercase());
+        assert_eq!(validated.into_rust_io().failed().first(), "NAME IS EMPTY");
+    }
+
+    #[test]
+    fn rio_zip_all() {
+        let rio_program = RustIO::zip_all(vec![validate_age(20), validate_age(30)]);
+        assert_eq!(rio_program.get(), vec![20, 30]);
+    }
+
+    #[test]
+    fn rio_zip_all_errors() {
+        let rio_program = RustIO::zip_all(vec![validate_age(20), validate_age(10), validate_age(5)]);
+        assert_eq!(rio_program.failed().len(), 2);
+    }
+}
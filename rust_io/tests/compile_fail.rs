@@ -0,0 +1,5 @@
+#[test]
+fn rio_macro_compile_fail() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,10 @@
+use rust_io::{rust_io, RustIO};
+
+fn main() {
+    let _rio_program: RustIO<i32, String> = rust_io! {
+         v <- RustIO::of(1);
+         yield v;
+         recover { 2 }
+         on_error { println!("error") }
+    };
+}
@@ -0,0 +1,9 @@
+use rust_io::{rust_io, RustIO};
+
+fn main() {
+    let _rio_program: RustIO<i32, String> = rust_io! {
+         v <- RustIO::of(1);
+         RustIO::of(v)
+         recover { 2 }
+    };
+}
@@ -23,7 +23,6 @@ pub mod race_futures;
 pub mod promise;
 mod union_type;
 mod lens;
-mod rust_io_impl;
 mod dependency_injection;
 pub mod cargo_workspace;
 pub mod idiomatic_rust;